@@ -0,0 +1,52 @@
+// Errors returned by the table's fallible methods. The enums are
+// `#[non_exhaustive]`, so new kinds can be added without breaking a `match`.
+// The crate always builds against `std`, so `std::error::Error` is
+// implemented unconditionally, which makes them work with `?` and `anyhow`.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InsertError {
+    // A new key met a full `fixed_capacity` table with this many slots.
+    TableFull { capacity: usize },
+}
+
+impl fmt::Display for InsertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InsertError::TableFull { capacity } => {
+                write!(f, "fixed-capacity table is full ({} slots)", capacity)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InsertError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReserveError {
+    // The slots needed for the requested entries would not fit in memory
+    // addressable by a `usize`.
+    CapacityOverflow,
+    // A `fixed_capacity` table with this many slots cannot grow to fit.
+    FixedCapacity { capacity: usize },
+}
+
+impl fmt::Display for ReserveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReserveError::CapacityOverflow => f.write_str("capacity overflow"),
+            ReserveError::FixedCapacity { capacity } => {
+                write!(
+                    f,
+                    "fixed-capacity table cannot grow past {} slots",
+                    capacity
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReserveError {}
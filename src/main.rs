@@ -1,203 +1,3768 @@
+pub mod error;
+
+use error::{InsertError, ReserveError};
+use std::borrow::{Borrow, Cow};
 use std::cmp::PartialEq;
 use std::fmt::Debug;
-use std::time::Instant;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::num::{NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-pub trait Hashable {
-    fn hash(&self) -> usize;
+pub trait StreamingHasher {
+    fn write(&mut self, bytes: &[u8]);
+    fn finish(&self) -> usize;
 }
 
-impl Hashable for String {
-    // http://www.cse.yorku.ca/~oz/hash.html
-    fn hash(&self) -> usize {
-        let mut hash: usize = 5381;
-        for c in self.bytes() {
-            hash = (hash << 5).wrapping_add(hash).wrapping_add(c.into()); /* hash * 33 + c */
+// http://www.cse.yorku.ca/~oz/hash.html
+//
+// Each byte does `hash = (hash << multiplier_shift) + hash + c`, starting from
+// `base`. `new` uses the classic DJB2 constants, 5381 and 5 (hash * 33 + c).
+#[derive(Debug, Clone)]
+pub struct DjbHasher {
+    base: usize,
+    multiplier_shift: u32,
+    hash: usize,
+}
+
+impl DjbHasher {
+    pub fn new() -> Self {
+        Self::with_params(5381, 5)
+    }
+
+    pub fn with_params(base: usize, multiplier_shift: u32) -> Self {
+        assert!(multiplier_shift < usize::BITS);
+        Self {
+            base,
+            multiplier_shift,
+            hash: base,
         }
-        hash
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    pub fn multiplier_shift(&self) -> u32 {
+        self.multiplier_shift
+    }
+
+    // One step of the recurrence with a whole word in place of a byte, which
+    // is how slices fold their element hashes.
+    fn write_word(&mut self, x: usize) {
+        self.hash = (self.hash << self.multiplier_shift)
+            .wrapping_add(self.hash)
+            .wrapping_add(x);
     }
 }
 
-impl Hashable for usize {
-    fn hash(&self) -> usize {
-        *self
+impl Default for DjbHasher {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-#[derive(Debug, Default, Clone)]
-struct HashCell<K, V> {
-    key: K,
-    value: V,
-    taken: bool,
+impl StreamingHasher for DjbHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &c in bytes {
+            self.write_word(c.into());
+        }
+    }
+
+    fn finish(&self) -> usize {
+        self.hash
+    }
 }
 
-#[derive(Debug)]
-pub struct HashTable<K, V> {
-    cells: Vec<HashCell<K, V>>,
-    taken_count: usize,
+// http://www.isthe.com/chongo/tech/comp/fnv/
+//
+// FNV-1a, an alternative to DJB2 that often spreads short strings better.
+#[derive(Debug, Clone)]
+pub struct Fnv1aHasher {
+    hash: usize,
 }
 
-impl<K, V> HashTable<K, V>
-where
-    K: Default + Clone + Hashable + PartialEq + Debug,
-    V: Default + Clone + Debug,
-{
+#[cfg(target_pointer_width = "64")]
+const FNV_OFFSET_BASIS: usize = 0xcbf29ce484222325;
+#[cfg(target_pointer_width = "64")]
+const FNV_PRIME: usize = 0x100000001b3;
+#[cfg(target_pointer_width = "32")]
+const FNV_OFFSET_BASIS: usize = 0x811c9dc5;
+#[cfg(target_pointer_width = "32")]
+const FNV_PRIME: usize = 0x01000193;
+
+impl Fnv1aHasher {
     pub fn new() -> Self {
-        const DEFAULT_VEC_CAPACITY: usize = 61; // prime in order to have good splits
-        Self::with_capacity(DEFAULT_VEC_CAPACITY)
+        Self {
+            hash: FNV_OFFSET_BASIS,
+        }
     }
+}
 
-    pub fn with_capacity(capacity: usize) -> Self {
-        Self {
-            cells: vec![HashCell::<K, V>::default(); capacity],
-            taken_count: 0,
+impl Default for Fnv1aHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamingHasher for Fnv1aHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &c in bytes {
+            self.hash = (self.hash ^ usize::from(c)).wrapping_mul(FNV_PRIME);
         }
     }
 
-    fn extend(&mut self) {
-        assert_eq!(self.taken_count, self.cells.len());
-        assert_ne!(self.cells.len(), 0);
+    fn finish(&self) -> usize {
+        self.hash
+    }
+}
 
-        let mut new_self = Self {
-            cells: vec![HashCell::<K, V>::default(); self.cells.len() * 2 + 1],
-            taken_count: 0,
-        };
+// The hasher a table streams its keys through, see `HashTable::with_hasher`.
+// Its current state is the starting point for every key, so a hasher that
+// has already absorbed some bytes salts every hash with them.
+#[derive(Debug, Clone)]
+pub enum TableHasher {
+    Djb(DjbHasher),
+    Fnv1a(Fnv1aHasher),
+}
 
-        for cell in self.cells.iter() {
-            if cell.taken {
-                new_self.insert(cell.key.clone(), cell.value.clone());
-            }
-        }
+impl From<DjbHasher> for TableHasher {
+    fn from(h: DjbHasher) -> Self {
+        TableHasher::Djb(h)
+    }
+}
 
-        *self = new_self;
+impl From<Fnv1aHasher> for TableHasher {
+    fn from(h: Fnv1aHasher) -> Self {
+        TableHasher::Fnv1a(h)
     }
+}
 
-    pub fn insert(&mut self, key: K, new_value: V) {
-        if let Some(old_val) = self.get_mut(&key) {
-            *old_val = new_value;
-            return;
+impl StreamingHasher for TableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        match self {
+            TableHasher::Djb(h) => h.write(bytes),
+            TableHasher::Fnv1a(h) => h.write(bytes),
         }
+    }
 
-        if self.taken_count == self.cells.len() {
-            self.extend();
+    fn finish(&self) -> usize {
+        match self {
+            TableHasher::Djb(h) => h.finish(),
+            TableHasher::Fnv1a(h) => h.finish(),
         }
+    }
+}
 
-        assert!(self.cells.len() > self.taken_count);
+pub trait Hashable {
+    fn hash(&self) -> usize;
 
-        let mut idx = key.hash() % self.cells.len();
-        while self.cells[idx].taken {
-            idx = (idx + 1) % self.cells.len();
-        }
+    // Feeds the key into `h` so that keys made of several parts can be hashed
+    // field by field into a single hasher. Defaults to writing `hash()`.
+    fn hash_into(&self, h: &mut impl StreamingHasher) {
+        h.write(&self.hash().to_ne_bytes());
+    }
+}
 
-        self.cells[idx].key = key;
-        self.cells[idx].value = new_value;
-        self.cells[idx].taken = true;
-        self.taken_count += 1;
+// Hashes exactly like its bytes as a `[u8]`. When streamed, the length goes
+// in after the bytes so adjacent string fields cannot run into each other.
+impl Hashable for str {
+    fn hash(&self) -> usize {
+        self.as_bytes().hash()
     }
 
-    fn get_index(&self, key: &K) -> Option<usize> {
-        let mut idx = key.hash() % self.cells.len();
-        for _ in 0..self.cells.len() {
-            if !self.cells[idx].taken {
-                break;
+    fn hash_into(&self, h: &mut impl StreamingHasher) {
+        h.write(self.as_bytes());
+        h.write(&self.len().to_ne_bytes());
+    }
+}
+
+impl Hashable for String {
+    fn hash(&self) -> usize {
+        self.as_str().hash()
+    }
+
+    fn hash_into(&self, h: &mut impl StreamingHasher) {
+        self.as_str().hash_into(h);
+    }
+}
+
+impl<T: Hashable + ?Sized> Hashable for &T {
+    fn hash(&self) -> usize {
+        (**self).hash()
+    }
+
+    fn hash_into(&self, h: &mut impl StreamingHasher) {
+        (**self).hash_into(h);
+    }
+}
+
+impl<T: Hashable + ?Sized> Hashable for Box<T> {
+    fn hash(&self) -> usize {
+        (**self).hash()
+    }
+
+    fn hash_into(&self, h: &mut impl StreamingHasher) {
+        (**self).hash_into(h);
+    }
+}
+
+// Borrowed and owned `Cow`s hash through the borrowed form, so
+// `Cow::Borrowed("x")` and `Cow::Owned("x".into())` hash the same.
+impl<B: Hashable + ToOwned + ?Sized> Hashable for Cow<'_, B> {
+    fn hash(&self) -> usize {
+        (**self).hash()
+    }
+
+    fn hash_into(&self, h: &mut impl StreamingHasher) {
+        (**self).hash_into(h);
+    }
+}
+
+// Lets `std::hash::Hash` values, such as `mem::Discriminant`, feed one of our
+// hashers.
+struct StdHashAdapter<'a, H>(&'a mut H);
+
+impl<H: StreamingHasher> std::hash::Hasher for StdHashAdapter<'_, H> {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.write(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0.finish() as u64
+    }
+}
+
+// Shortcut for enum keys: implement `payload_hash` for the variant's data and
+// forward `Hashable::hash` to `hash_by_discriminant`. The variant itself is
+// mixed in, so two variants with equal payloads still hash apart.
+pub trait HashableByDiscriminant: Sized {
+    fn payload_hash(&self) -> usize;
+
+    fn hash_by_discriminant(&self) -> usize {
+        let mut h = DjbHasher::new();
+        std::hash::Hash::hash(&std::mem::discriminant(self), &mut StdHashAdapter(&mut h));
+        h.write(&self.payload_hash().to_ne_bytes());
+        h.finish()
+    }
+}
+
+// `Hashable` is not object safe because `hash_into` is generic over the
+// hasher. `DynHashable` is the `dyn`-compatible part of it, implemented for
+// every sized `Hashable`, so a `Box<dyn DynHashable>` can pick the hashing at
+// runtime and still be passed to code generic over `Hashable`. It hashes
+// exactly like the value inside. It is not a table key by itself, though:
+// keys must also be `Clone`, `PartialEq` and `Debug`, and trait objects are
+// none of those.
+pub trait DynHashable {
+    fn dyn_hash(&self) -> usize;
+}
+
+impl<T: Hashable> DynHashable for T {
+    fn dyn_hash(&self) -> usize {
+        self.hash()
+    }
+}
+
+impl Hashable for dyn DynHashable + '_ {
+    fn hash(&self) -> usize {
+        self.dyn_hash()
+    }
+}
+
+macro_rules! impl_hashable_for_uint {
+    ($($t:ty),*) => {
+        $(
+            impl Hashable for $t {
+                fn hash(&self) -> usize {
+                    *self as usize
+                }
             }
+        )*
+    };
+}
+
+impl_hashable_for_uint!(u8, u16, u32, u64, usize);
+
+// Every `()` is equal, so they all share one hash.
+impl Hashable for () {
+    fn hash(&self) -> usize {
+        0
+    }
+}
+
+impl<T: Hashable> Hashable for (T,) {
+    fn hash(&self) -> usize {
+        self.0.hash()
+    }
+
+    fn hash_into(&self, h: &mut impl StreamingHasher) {
+        self.0.hash_into(h);
+    }
+}
+
+// `as usize` would drop the high 64 bits (96 on 32-bit targets). Instead the
+// high half is mixed in through an odd multiply, which is a bijection, and
+// the result folded once more so 32-bit targets keep every bit's influence.
+impl Hashable for u128 {
+    fn hash(&self) -> usize {
+        let lo = *self as u64;
+        let hi = (*self >> 64) as u64;
+        let folded = lo ^ hi.wrapping_mul(0x9e3779b97f4a7c15);
+        (folded ^ (folded >> 32)) as usize
+    }
+}
+
+impl Hashable for i128 {
+    fn hash(&self) -> usize {
+        (*self as u128).hash()
+    }
+}
 
-            if self.cells[idx].key == *key {
-                return Some(idx);
+macro_rules! impl_hashable_for_nonzero {
+    ($($t:ty),*) => {
+        $(
+            impl Hashable for $t {
+                fn hash(&self) -> usize {
+                    self.get().hash()
+                }
             }
+        )*
+    };
+}
 
-            idx = (idx + 1) % self.cells.len();
+impl_hashable_for_nonzero!(NonZeroU8, NonZeroU16, NonZeroU32, NonZeroU64, NonZeroUsize);
+
+// `DjbHasher`'s fold, but over element hashes, so element order matters. The
+// length is folded in last, so that slices used as parts of a composite key
+// cannot trade elements, e.g. ([1, 2], [3]) and ([1], [2, 3]). For bytes this
+// is DJB2 over the bytes, then the length.
+impl<T: Hashable> Hashable for [T] {
+    fn hash(&self) -> usize {
+        let mut h = DjbHasher::new();
+        for x in self {
+            h.write_word(x.hash());
         }
+        h.write_word(self.len());
+        h.finish()
+    }
+}
 
-        None
+// A `Vec` hashes like the slice it holds.
+impl<T: Hashable> Hashable for Vec<T> {
+    fn hash(&self) -> usize {
+        self.as_slice().hash()
     }
 
-    pub fn get(&self, key: &K) -> Option<&V> {
-        if let Some(idx) = self.get_index(key) {
-            Some(&self.cells[idx].value)
-        } else {
-            None
+    fn hash_into(&self, h: &mut impl StreamingHasher) {
+        self.as_slice().hash_into(h);
+    }
+}
+
+const DEFAULT_VEC_CAPACITY: usize = 61; // prime in order to have good splits
+const MIN_OPS_BETWEEN_AUTO_RESIZES: usize = 64;
+
+impl Hashable for Ipv4Addr {
+    fn hash(&self) -> usize {
+        self.octets()[..].hash()
+    }
+}
+
+impl Hashable for Ipv6Addr {
+    fn hash(&self) -> usize {
+        self.octets()[..].hash()
+    }
+}
+
+impl Hashable for IpAddr {
+    fn hash(&self) -> usize {
+        match self {
+            IpAddr::V4(ip) => ip.hash(),
+            IpAddr::V6(ip) => ip.hash(),
         }
     }
+}
 
-    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
-        if let Some(idx) = self.get_index(key) {
-            Some(&mut self.cells[idx].value)
-        } else {
-            None
+impl Hashable for SocketAddr {
+    fn hash(&self) -> usize {
+        let mut h = DjbHasher::new();
+        self.hash_into(&mut h);
+        h.finish()
+    }
+
+    fn hash_into(&self, h: &mut impl StreamingHasher) {
+        self.ip().hash_into(h);
+        h.write(&self.port().to_ne_bytes());
+    }
+}
+
+// `Path` equality compares components, so "a/b" and "a//b/" are the same
+// key. Hashing the raw bytes would split them, so the components are hashed
+// instead, each followed by its length so "ab" and "a/b" differ.
+impl Hashable for Path {
+    fn hash(&self) -> usize {
+        let mut h = DjbHasher::new();
+        self.hash_into(&mut h);
+        h.finish()
+    }
+
+    fn hash_into(&self, h: &mut impl StreamingHasher) {
+        for component in self.components() {
+            let bytes = component.as_os_str().as_encoded_bytes();
+            h.write(bytes);
+            h.write(&bytes.len().to_ne_bytes());
         }
     }
+}
 
-    pub fn debug_dump(&self) {
-        println!("----------------------------------------------------------");
-        println!("  Table Len {}", self.cells.len());
-        println!("  Taken Count {}", self.taken_count);
-        println!("  Data");
-        for (i, c) in self.cells.iter().enumerate() {
-            if c.taken {
-                println!("    ({})      {:?} => {:?}", i, c.key, c.value);
-            } else {
-                println!("    ({})      X", i);
+impl Hashable for PathBuf {
+    fn hash(&self) -> usize {
+        self.as_path().hash()
+    }
+
+    fn hash_into(&self, h: &mut impl StreamingHasher) {
+        self.as_path().hash_into(h);
+    }
+}
+
+impl Hashable for Duration {
+    fn hash(&self) -> usize {
+        let mut h = DjbHasher::new();
+        self.hash_into(&mut h);
+        h.finish()
+    }
+
+    fn hash_into(&self, h: &mut impl StreamingHasher) {
+        h.write(&self.as_secs().to_ne_bytes());
+        h.write(&self.subsec_nanos().to_ne_bytes());
+    }
+}
+
+// Hashed as a signed offset from the epoch: times before it hash their
+// distance to it under a different tag than times after it.
+impl Hashable for SystemTime {
+    fn hash(&self) -> usize {
+        let mut h = DjbHasher::new();
+        self.hash_into(&mut h);
+        h.finish()
+    }
+
+    fn hash_into(&self, h: &mut impl StreamingHasher) {
+        match self.duration_since(UNIX_EPOCH) {
+            Ok(since) => {
+                h.write(&[0]);
+                since.hash_into(h);
+            }
+            Err(e) => {
+                h.write(&[1]);
+                e.duration().hash_into(h);
             }
         }
-        println!("----------------------------------------------------------");
     }
 }
 
-fn benchmark_our_vergin_table(n: usize) {
-    let start = Instant::now();
+// The smallest prime >= `n`, by trial division.
+fn next_prime(n: usize) -> usize {
+    let is_prime = |n: usize| {
+        n >= 2
+            && (2..)
+                .take_while(|&d| d <= n / d)
+                .all(|d| !n.is_multiple_of(d))
+    };
+    (n..)
+        .find(|&n| is_prime(n))
+        .expect("no prime left in usize")
+}
 
-    let mut table = HashTable::<usize, usize>::new();
-    for _ in 0..n {
-        let n = rand::random::<usize>();
-        if let Some(val) = table.get_mut(&n) {
-            *val += 1; // this might overflow
+// The capacity a full table of `capacity` slots grows to.
+fn grown_capacity(capacity: usize) -> usize {
+    capacity
+        .checked_mul(2)
+        .and_then(|c| c.checked_add(1))
+        .expect("capacity overflow")
+}
+
+// Raw `f64` keys are a trap: NaN != NaN, so a NaN key could never be found
+// again. `TotalF64` compares with `f64::total_cmp` instead, after collapsing
+// every NaN into one canonical NaN, so equality is reflexive and agrees with
+// the hash. Note that under this order -0.0 and 0.0 are different keys.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TotalF64(pub f64);
+
+impl TotalF64 {
+    fn canonical(self) -> f64 {
+        if self.0.is_nan() {
+            f64::NAN
         } else {
-            table.insert(n, n);
+            self.0
         }
-        assert_eq!(*table.get(&n).unwrap(), n);
     }
+}
 
-    let duration = start.elapsed();
-    println!("Time elapsed in in vergin table is: {:?}", duration);
+impl PartialEq for TotalF64 {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
 }
 
-fn benchmark_std_chad_table(n: usize) {
-    let start = Instant::now();
+impl Eq for TotalF64 {}
 
-    let mut table = std::collections::HashMap::<usize, usize>::new();
-    for _ in 0..n {
-        let n = rand::random::<usize>();
-        if let Some(val) = table.get_mut(&n) {
-            *val += 1; // this might overflow
-        } else {
-            table.insert(n, n);
-        }
-        assert_eq!(*table.get(&n).unwrap(), n);
+impl PartialOrd for TotalF64 {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
     }
+}
 
-    let duration = start.elapsed();
-    println!("Time elapsed in in chad table is: {:?}", duration);
+impl Ord for TotalF64 {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.canonical().total_cmp(&other.canonical())
+    }
 }
 
-fn main() {
-    let mut table = HashTable::<String, String>::with_capacity(11);
-    for i in 0..11 {
-        table.insert(format!("{}", i), format!("{}", 100_000 + i));
+impl Hashable for TotalF64 {
+    fn hash(&self) -> usize {
+        self.canonical().to_bits().hash()
     }
+}
 
-    // table.debug_dump();
-    table.insert("69".to_string(), "69".to_string());
-    // table.debug_dump();
+// An occupied slot. Empty slots are `None`, so keys and values need no
+// `Default` placeholder, and types like `NonZeroU64` or `IpAddr` can be keys.
+#[derive(Debug, Clone)]
+struct HashCell<K, V> {
+    key: K,
+    value: V,
+    // Stamped by `place`, so a `Handle` can tell its entry from a later one.
+    generation: NonZeroU32,
+}
 
-    for i in 0..11 {
-        let key = format!("{}", i);
-        assert_eq!(*table.get(&key).unwrap(), format!("{}", 100_000 + i));
+// What one physical slot holds.
+type Slot<K, V> = Option<HashCell<K, V>>;
+
+// Set-like tables (`V = ()`) pay nothing per slot for the value, and since
+// the generation is never zero, `Option` keeps the empty marker in its niche.
+const _: () = assert!(
+    std::mem::size_of::<Option<HashCell<usize, ()>>>() == std::mem::size_of::<(usize, bool)>()
+);
+
+fn empty_cells<K, V>(capacity: usize) -> Vec<Option<HashCell<K, V>>> {
+    std::iter::repeat_with(|| None).take(capacity).collect()
+}
+
+// Free functions rather than methods, so that they can work on either buffer
+// of a table that is migrating (see `Migration`) while it is borrowed mutably.
+fn hash_with<Q: Hashable + ?Sized>(hasher: &Option<TableHasher>, key: &Q) -> usize {
+    match hasher {
+        None => key.hash(),
+        Some(hasher) => {
+            let mut h = hasher.clone();
+            key.hash_into(&mut h);
+            h.finish()
+        }
     }
+}
 
-    assert_eq!(*table.get(&("69".to_string())).unwrap(), "69".to_string());
+fn find_slot<K, V, Q>(cells: &[Slot<K, V>], hash: usize, key: &Q) -> Option<usize>
+where
+    K: Borrow<Q>,
+    Q: PartialEq + ?Sized,
+{
+    // A table may be created without slots, and holds nothing until it grows.
+    if cells.is_empty() {
+        return None;
+    }
 
-    const N: usize = 100_000;
+    let mut idx = hash % cells.len();
+    for _ in 0..cells.len() {
+        match &cells[idx] {
+            None => break,
+            Some(cell) if cell.key.borrow() == key => return Some(idx),
+            Some(_) => {}
+        }
 
-    benchmark_our_vergin_table(N);
+        idx = (idx + 1) % cells.len();
+    }
+
+    None
+}
+
+// Backward-shift deletion: after `hole` is emptied, walk the rest of the
+// cluster and pull back every entry whose home slot does not lie between
+// the hole and its current position, so probing never stops early.
+fn shift_back<K: Hashable, V>(
+    cells: &mut [Slot<K, V>],
+    mut hole: usize,
+    hasher: &Option<TableHasher>,
+) {
+    let len = cells.len();
+    let mut next = (hole + 1) % len;
+    while let Some(moving) = &cells[next] {
+        let home = hash_with(hasher, &moving.key) % len;
+        if (next + len - home) % len >= (next + len - hole) % len {
+            cells.swap(hole, next);
+            hole = next;
+        }
+        next = (next + 1) % len;
+    }
+}
+
+// Two distinct elements of one slice, mutably.
+fn pair_mut<T>(items: &mut [T], i: usize, j: usize) -> (&mut T, &mut T) {
+    let (left, right) = items.split_at_mut(i.max(j));
+    let (lo, hi) = (&mut left[i.min(j)], &mut right[0]);
+    if i < j {
+        (lo, hi)
+    } else {
+        (hi, lo)
+    }
+}
+
+// The old buffer of an incremental resize, see
+// `HashTable::set_incremental_resize`. It stays a valid table on its own,
+// with entries only ever leaving it. They leave from the front: each step
+// takes the entry at `cursor` out with a backward-shift removal and places it
+// in the new buffer, and only an empty slot moves the cursor on. So every
+// slot before the cursor is empty, and lookups need no special casing.
+#[derive(Debug)]
+struct Migration<K, V> {
+    cells: Vec<Slot<K, V>>,
+    cursor: usize,
+    remaining: usize,
+}
+
+// Slots an incremental resize inspects per insert or removal. Growth doubles
+// the capacity, so at least as many inserts as the old buffer has slots
+// follow before the next growth, and the migration is long done by then.
+const MIGRATION_STEP: usize = 4;
+
+// One physical slot of a table, as yielded by `HashTable::raw_slots`.
+// Removal shifts entries back instead of leaving tombstones, so a slot is
+// always either empty or occupied.
+#[derive(Debug)]
+pub enum SlotState<'a, K, V> {
+    Empty,
+    Occupied(&'a K, &'a V),
+}
+
+// A cheap reference to one entry, from `HashTable::handle`. It survives
+// inserts and removals of other keys: a removal may shift the entry back
+// towards its home slot, but never past it, so the handle remembers how far
+// from home the entry was and follows it back. It goes stale when the entry
+// itself is removed (reinserting makes a new entry), or on anything that
+// rehashes the table: a resize, `retain`, `retain_slots`, `remove_slots` or
+// `extract_if`. An incremental resize counts as soon as it starts, while the
+// entry is still in the old buffer. A stale handle finds nothing rather than
+// another entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Handle {
+    slot: usize,
+    distance_from_home: usize,
+    generation: NonZeroU32,
+}
+
+// A snapshot of a table's health, from `HashTable::stats`. A probe length is
+// the number of slots a lookup of a present key inspects, so 1 means the key
+// sits in its home slot; `collision_count` counts the keys that don't.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStats {
+    pub len: usize,
+    pub capacity: usize,
+    pub load_factor: f64,
+    pub resize_count: usize,
+    pub collision_count: usize,
+    pub avg_probe_length: f64,
+    pub max_probe_length: usize,
+}
+
+// `H` is where capacity changes are reported, see `ResizeObserver`. Only
+// the wrappers below pick anything but the default.
+#[derive(Debug)]
+pub struct HashTable<K, V, H = Option<ResizeHook>> {
+    cells: Vec<Option<HashCell<K, V>>>,
+    taken_count: usize,
+    resize_count: usize,
+    fixed: bool,
+    min_capacity: usize,
+    auto_shrink: bool,
+    ops_since_resize: usize,
+    on_resize: H,
+    next_generation: NonZeroU32,
+    // `None` hashes keys with `Hashable::hash`, see `with_hasher`.
+    hasher: Option<TableHasher>,
+    incremental_resize: bool,
+    // The buffer being drained while an incremental resize is in progress.
+    // Its slots are numbered after those of `cells`, and `taken_count`
+    // counts its entries too.
+    migration: Option<Migration<K, V>>,
+}
+
+// Told about every change of a table's capacity. Public only because it
+// appears in `HashTable`'s signature; tables can only be built with the
+// implementations below.
+pub trait ResizeObserver {
+    fn resized(&mut self, old_capacity: usize, capacity: usize);
+}
+
+// A boxed closure can't derive `Debug`, so the hook gets a placeholder one.
+// The closure need not be `Send` or `Sync`, so that single-threaded metrics
+// such as `Rc<Cell<_>>` counters work. That makes `HashTable` itself neither.
+pub struct ResizeHook(Box<dyn FnMut(usize, usize)>);
+
+impl Debug for ResizeHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ResizeHook")
+    }
+}
+
+impl ResizeObserver for Option<ResizeHook> {
+    fn resized(&mut self, old_capacity: usize, capacity: usize) {
+        if let Some(hook) = self {
+            (hook.0)(old_capacity, capacity);
+        }
+    }
+}
+
+// The observer of tables that can never get a hook. `FrozenHashTable` and
+// `ConcurrentHashTable` hold such tables, so they are `Send` and `Sync`
+// whenever their keys and values are, without any `unsafe`.
+#[derive(Debug, Default)]
+pub struct NoHook;
+
+impl ResizeObserver for NoHook {
+    fn resized(&mut self, _: usize, _: usize) {}
+}
+
+impl<K, V> Default for HashTable<K, V>
+where
+    K: Clone + Hashable + PartialEq + Debug,
+    V: Clone + Debug,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> HashTable<K, V>
+where
+    K: Clone + Hashable + PartialEq + Debug,
+    V: Clone + Debug,
+{
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_VEC_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::empty(capacity)
+    }
+
+    // Like `with_capacity`, but rounds `capacity` up to the next prime (which
+    // splits keys better) and returns the capacity actually allocated.
+    pub fn with_capacity_rounded(capacity: usize) -> (Self, usize) {
+        let capacity = next_prime(capacity);
+        (Self::with_capacity(capacity), capacity)
+    }
+
+    // Same as collecting `entries`: sized from the size hint, and the last
+    // value wins for duplicate keys.
+    pub fn with_entries<I: IntoIterator<Item = (K, V)>>(entries: I) -> Self {
+        entries.into_iter().collect()
+    }
+
+    // A table that never grows: once all `capacity` slots are taken, inserts
+    // of new keys are rejected. Updates to existing keys still succeed. With a
+    // `capacity` of 0 every new key is rejected.
+    pub fn fixed_capacity(capacity: usize) -> Self {
+        Self {
+            fixed: true,
+            ..Self::with_capacity(capacity)
+        }
+    }
+
+    // A table that streams every key through `hasher` with `hash_into`,
+    // instead of using `Hashable::hash`. Lets string-keyed tables pick FNV-1a
+    // over the default DJB2, or DJB2 with other parameters. Costs a clone of
+    // the hasher state per hash.
+    pub fn with_hasher(hasher: impl Into<TableHasher>) -> Self {
+        Self {
+            hasher: Some(hasher.into()),
+            ..Self::new()
+        }
+    }
+
+    // A table whose key hashes are salted with `tag`, so the same keys in
+    // tables with different tags land in unrelated slots and collide with
+    // different neighbours. Keys are streamed through FNV-1a after the tag.
+    pub fn with_domain(tag: &[u8]) -> Self {
+        let mut hasher = Fnv1aHasher::new();
+        hasher.write(tag);
+        hasher.write(&tag.len().to_ne_bytes());
+        Self::with_hasher(hasher)
+    }
+
+    // Calls `f(old_capacity, new_capacity)` every time the table's capacity
+    // changes, whether it grew on insert or was reserved or shrunk
+    // explicitly. Replaces any previous callback. Costs nothing when unset.
+    pub fn on_resize<F: FnMut(usize, usize) + 'static>(&mut self, f: F) {
+        self.on_resize = Some(ResizeHook(Box::new(f)));
+    }
+}
+
+impl<K, V, H> HashTable<K, V, H>
+where
+    K: Clone + Hashable + PartialEq + Debug,
+    V: Clone + Debug,
+    H: ResizeObserver + Default,
+{
+    fn empty(capacity: usize) -> Self {
+        Self {
+            cells: empty_cells(capacity),
+            taken_count: 0,
+            resize_count: 0,
+            fixed: false,
+            min_capacity: DEFAULT_VEC_CAPACITY,
+            auto_shrink: false,
+            ops_since_resize: 0,
+            on_resize: H::default(),
+            next_generation: NonZeroU32::MIN,
+            hasher: None,
+            incremental_resize: false,
+            migration: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.taken_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.taken_count == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.cells.len()
+    }
+
+    // The table has no load factor: it only grows once every slot is taken,
+    // so the entry count at which the next new key resizes is the capacity.
+    pub fn resize_threshold(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.taken_count >= self.resize_threshold()
+    }
+
+    // Expected probes for a successful lookup under linear probing, from the
+    // load factor alone: (1 + 1 / (1 - a)) / 2. O(1), but only an estimate.
+    // The formula blows up as the table fills, so it is capped at the
+    // capacity, which no lookup can exceed. A table without slots gives 0.
+    pub fn estimated_avg_probe_length(&self) -> f64 {
+        let capacity = self.cells.len();
+        if capacity == 0 {
+            return 0.0;
+        }
+        let load_factor = self.taken_count as f64 / capacity as f64;
+        ((1.0 + 1.0 / (1.0 - load_factor)) / 2.0).min(capacity as f64)
+    }
+
+    // Measures the actual probe lengths, unlike `estimated_avg_probe_length`,
+    // so it walks every slot.
+    pub fn stats(&self) -> TableStats {
+        let capacity = self.cells.len();
+        let mut collision_count = 0;
+        let mut total_probe_length = 0;
+        let mut max_probe_length = 0;
+
+        // Entries still in a migration's old buffer are probed for there.
+        for cells in self.buffers() {
+            let len = cells.len();
+            for (i, cell) in cells.iter().enumerate() {
+                let Some(cell) = cell else {
+                    continue;
+                };
+                let home = self.prehash(&cell.key) % len;
+                let probe_length = (i + len - home) % len + 1;
+                if probe_length > 1 {
+                    collision_count += 1;
+                }
+                total_probe_length += probe_length;
+                max_probe_length = max_probe_length.max(probe_length);
+            }
+        }
+
+        TableStats {
+            len: self.taken_count,
+            capacity,
+            load_factor: if capacity == 0 {
+                0.0
+            } else {
+                self.taken_count as f64 / capacity as f64
+            },
+            resize_count: self.resize_count,
+            collision_count,
+            avg_probe_length: if self.taken_count == 0 {
+                0.0
+            } else {
+                total_probe_length as f64 / self.taken_count as f64
+            },
+            max_probe_length,
+        }
+    }
+
+    // Grows the table to exactly `capacity` slots, without any rounding. Never
+    // shrinks, like `Vec::reserve_exact`: it does nothing if the table is
+    // already at least that big, or is a `fixed_capacity` table. Inserting
+    // more than `capacity` entries afterwards still grows the table as usual.
+    pub fn reserve_exact(&mut self, capacity: usize) {
+        if self.fixed || capacity <= self.cells.len() {
+            return;
+        }
+        if self.incremental_resize {
+            self.migrate_to(capacity);
+        } else {
+            self.resize(capacity);
+        }
+    }
+
+    // Makes room for `additional` more entries, with the headroom growth
+    // leaves, so inserting them does not resize. Fails, changing nothing, if
+    // the slots would not fit in memory, or if a `fixed_capacity` table is
+    // too small. Running out of memory while allocating still aborts.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), ReserveError> {
+        let len = self
+            .taken_count
+            .checked_add(additional)
+            .ok_or(ReserveError::CapacityOverflow)?;
+        if self.fixed {
+            return if len <= self.cells.len() {
+                Ok(())
+            } else {
+                Err(ReserveError::FixedCapacity {
+                    capacity: self.cells.len(),
+                })
+            };
+        }
+
+        let capacity = len
+            .checked_mul(2)
+            .and_then(|c| c.checked_add(1))
+            .ok_or(ReserveError::CapacityOverflow)?
+            .max(self.min_capacity);
+        let bytes = capacity.checked_mul(std::mem::size_of::<Slot<K, V>>());
+        if bytes.is_none_or(|b| b > isize::MAX as usize) {
+            return Err(ReserveError::CapacityOverflow);
+        }
+        self.reserve_exact(capacity);
+        Ok(())
+    }
+
+    // Off by default. When on, growing the table (on insert, or through
+    // `reserve_exact` and so `extend`) only allocates the bigger buffer; the
+    // entries move over a few at a time on each later insert or removal, so
+    // no single call pays for rehashing the whole table. Lookups search both
+    // buffers in the meantime. Other resizes, and anything that rehashes,
+    // still finish any migration in progress at once. Turning the mode off
+    // finishes it as well.
+    pub fn set_incremental_resize(&mut self, incremental_resize: bool) {
+        self.incremental_resize = incremental_resize;
+        if !incremental_resize {
+            self.finish_migration();
+        }
+    }
+
+    // Whether an incremental resize is still moving entries over.
+    pub fn is_migrating(&self) -> bool {
+        self.migration.is_some()
+    }
+
+    // Starts an incremental resize to `capacity` slots, finishing any earlier
+    // one first so that at most one old buffer is ever live.
+    fn migrate_to(&mut self, capacity: usize) {
+        assert!(capacity >= self.taken_count);
+
+        self.finish_migration();
+        let old_cells = std::mem::replace(&mut self.cells, empty_cells(capacity));
+        self.capacity_changed(old_cells.len(), capacity);
+        if self.taken_count > 0 {
+            self.migration = Some(Migration {
+                cells: old_cells,
+                cursor: 0,
+                remaining: self.taken_count,
+            });
+        }
+    }
+
+    // Moves entries out of the old buffer until `steps` slots have been
+    // inspected or it is empty.
+    fn migrate(&mut self, steps: usize) {
+        for _ in 0..steps {
+            let Some(migration) = &mut self.migration else {
+                return;
+            };
+            let cursor = migration.cursor;
+            let Some(cell) = migration.cells[cursor].take() else {
+                migration.cursor += 1;
+                continue;
+            };
+
+            shift_back(&mut migration.cells, cursor, &self.hasher);
+            migration.remaining -= 1;
+            if migration.remaining == 0 {
+                self.migration = None;
+            }
+            self.taken_count -= 1;
+            self.place(cell.key, cell.value);
+        }
+    }
+
+    fn finish_migration(&mut self) {
+        if let Some(migration) = &self.migration {
+            let slots = migration.cells.len() + migration.remaining;
+            self.migrate(slots);
+        }
+        debug_assert!(self.migration.is_none());
+    }
+
+    // The capacity below which shrinking stops, so a table that is regularly
+    // refilled does not shrink to a size it immediately outgrows. Defaults to
+    // the default capacity. Does not grow the table by itself.
+    pub fn min_capacity(&self) -> usize {
+        self.min_capacity
+    }
+
+    pub fn set_min_capacity(&mut self, min_capacity: usize) {
+        assert_ne!(min_capacity, 0);
+        self.min_capacity = min_capacity;
+    }
+
+    // Off by default. When on, removals (`take`, `retain`, `retain_slots`,
+    // `remove_slots`, `extract_if`, and so `ConcurrentHashTable::remove` on
+    // its shards) that leave the table less than a quarter full shrink it
+    // to 2 * len + 1 slots, the shape growth leaves it in, so it is not full
+    // straight after shrinking. Never goes below `min_capacity`, and never
+    // applies to `fixed_capacity` tables.
+    //
+    // Shrinking at 1/4 full to about 1/2 full, while growth only happens at
+    // 100%, keeps a table hovering near either boundary from thrashing. On
+    // top of that, at least `MIN_OPS_BETWEEN_AUTO_RESIZES` entries must have
+    // been inserted or removed since the last capacity change. Growth itself
+    // cannot wait: a full table has nowhere to put the next key.
+    pub fn set_auto_shrink(&mut self, auto_shrink: bool) {
+        self.auto_shrink = auto_shrink;
+    }
+
+    fn maybe_auto_shrink(&mut self) {
+        if self.auto_shrink
+            && !self.fixed
+            && self.taken_count < self.cells.len() / 4
+            && self.ops_since_resize >= MIN_OPS_BETWEEN_AUTO_RESIZES
+        {
+            self.shrink_to_fit();
+        }
+    }
+
+    // Shrinks the table to 2 * len + 1 slots, for undoing an over-reservation.
+    // Like auto-shrinking, it leaves room so the table is not full straight
+    // away. Never goes below `min_capacity`, and does nothing to a
+    // `fixed_capacity` table.
+    pub fn shrink_to_fit(&mut self) {
+        if self.fixed {
+            return;
+        }
+        let capacity = self.roomy_capacity(self.taken_count);
+        if capacity < self.cells.len() {
+            self.resize(capacity);
+        }
+    }
+
+    // The capacity that holds `len` entries in the shape growth leaves a
+    // table in, at least `min_capacity`.
+    fn roomy_capacity(&self, len: usize) -> usize {
+        len.saturating_mul(2)
+            .saturating_add(1)
+            .max(self.min_capacity)
+    }
+
+    fn resize(&mut self, capacity: usize) {
+        assert!(capacity >= self.taken_count);
+
+        self.rehash_retaining(capacity, |_, _, _| true);
+    }
+
+    // Swaps in an empty buffer of `capacity` slots and hands back the old one,
+    // followed by any migration's old buffer, so slots keep the numbering of
+    // `raw_slots`. Every change of capacity goes through here or `migrate_to`,
+    // both of which call `on_resize` through `capacity_changed`.
+    fn replace_cells(&mut self, capacity: usize) -> Vec<Option<HashCell<K, V>>> {
+        let mut old_cells = std::mem::replace(&mut self.cells, empty_cells(capacity));
+        self.taken_count = 0;
+
+        self.capacity_changed(old_cells.len(), capacity);
+        if let Some(migration) = self.migration.take() {
+            old_cells.extend(migration.cells);
+        }
+
+        old_cells
+    }
+
+    fn capacity_changed(&mut self, old_capacity: usize, capacity: usize) {
+        if old_capacity != capacity {
+            self.ops_since_resize = 0;
+            self.on_resize.resized(old_capacity, capacity);
+        }
+    }
+
+    // Moves every entry `keep` accepts into a fresh buffer of `capacity` slots.
+    // `keep` also gets the slot the entry occupied in the old buffer.
+    fn rehash_retaining<F>(&mut self, capacity: usize, mut keep: F)
+    where
+        F: FnMut(usize, &K, &mut V) -> bool,
+    {
+        let old_taken_count = self.taken_count;
+        let old_cells = self.replace_cells(capacity);
+
+        for (i, cell) in old_cells.into_iter().enumerate() {
+            if let Some(mut cell) = cell {
+                if keep(i, &cell.key, &mut cell.value) {
+                    self.place(cell.key, cell.value);
+                }
+            }
+        }
+
+        self.ops_since_resize += old_taken_count - self.taken_count;
+    }
+
+    fn grow(&mut self) {
+        assert_eq!(self.taken_count, self.cells.len());
+
+        let capacity = grown_capacity(self.cells.len());
+        if self.incremental_resize {
+            self.migrate_to(capacity);
+        } else {
+            self.resize(capacity);
+        }
+        self.resize_count += 1;
+    }
+
+    // Number of times the table grew because an insert found it full.
+    // Explicit `reserve_exact` calls are not counted.
+    pub fn resize_count(&self) -> usize {
+        self.resize_count
+    }
+
+    // Returns false, storing nothing, only when `key` is new and the table is
+    // a full `fixed_capacity` table.
+    pub fn insert(&mut self, key: K, new_value: V) -> bool {
+        if let Some(old_val) = self.get_mut(&key) {
+            *old_val = new_value;
+            self.migrate(MIGRATION_STEP);
+            return true;
+        }
+
+        if self.rejects_new_keys() {
+            return false;
+        }
+
+        self.insert_new(key, new_value);
+        true
+    }
+
+    // Like `insert`, but a new key that a full `fixed_capacity` table cannot
+    // store is reported as an error instead of `false`.
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<(), InsertError> {
+        if self.insert(key, value) {
+            Ok(())
+        } else {
+            Err(InsertError::TableFull {
+                capacity: self.cells.len(),
+            })
+        }
+    }
+
+    fn rejects_new_keys(&self) -> bool {
+        self.fixed && self.is_full()
+    }
+
+    // Like `insert`, but on a match the stored key is replaced too, and the old
+    // pair is returned. Matters when `PartialEq` ignores part of the key. A
+    // new key is inserted and gives `None`. Since `None` has to mean the key
+    // was stored, a new key panics on a full `fixed_capacity` table, like
+    // `get_or_insert_with_key`, rather than being dropped.
+    pub fn replace(&mut self, key: K, value: V) -> Option<(K, V)> {
+        match self.get_index(&key) {
+            Some(idx) => {
+                let cell = self.occupied_mut(idx);
+                let old_key = std::mem::replace(&mut cell.key, key);
+                let old_value = std::mem::replace(&mut cell.value, value);
+                Some((old_key, old_value))
+            }
+            None => {
+                self.insert_new(key, value);
+                None
+            }
+        }
+    }
+
+    // Inserts only if `key` is missing. Returns false, leaving the existing
+    // value untouched, if the key was already present (or could not be added
+    // to a full `fixed_capacity` table).
+    pub fn insert_if_absent(&mut self, key: K, value: V) -> bool {
+        if self.get_index(&key).is_some() || self.rejects_new_keys() {
+            return false;
+        }
+
+        self.insert_new(key, value);
+        true
+    }
+
+    // Inserts a key that is known not to be in the table and returns the slot
+    // it landed in.
+    fn insert_new(&mut self, key: K, value: V) -> usize {
+        if self.is_full() {
+            assert!(!self.fixed, "fixed-capacity table is full");
+            self.grow();
+        }
+
+        self.ops_since_resize += 1;
+        let idx = self.place(key, value);
+        // Migrated entries only fill free slots, so `idx` stays valid.
+        self.migrate(MIGRATION_STEP);
+        idx
+    }
+
+    // Writes `key` into its first free slot. Unlike `insert_new` it neither
+    // grows the table nor counts as an operation, which is what rehashing
+    // existing entries needs.
+    fn place(&mut self, key: K, value: V) -> usize {
+        let idx = self.first_free_slot(&key);
+        self.cells[idx] = Some(HashCell {
+            key,
+            value,
+            generation: self.next_generation,
+        });
+        self.next_generation =
+            NonZeroU32::new(self.next_generation.get().wrapping_add(1)).unwrap_or(NonZeroU32::MIN);
+        self.taken_count += 1;
+        idx
+    }
+
+    // The buffers in slot order: `cells`, then a migration's old buffer.
+    fn buffers(&self) -> impl Iterator<Item = &[Slot<K, V>]> {
+        std::iter::once(&self.cells[..]).chain(self.migration.as_ref().map(|m| &m.cells[..]))
+    }
+
+    fn slot_count(&self) -> usize {
+        self.buffers().map(<[_]>::len).sum()
+    }
+
+    // The buffer that physical slot `slot` lives in, and its index there.
+    fn buffer_of(&self, slot: usize) -> Option<(&[Slot<K, V>], usize)> {
+        match slot.checked_sub(self.cells.len()) {
+            None => Some((&self.cells, slot)),
+            Some(i) => {
+                let cells = &self.migration.as_ref()?.cells;
+                (i < cells.len()).then_some((&cells[..], i))
+            }
+        }
+    }
+
+    fn cell_at(&self, slot: usize) -> Option<&HashCell<K, V>> {
+        let (cells, i) = self.buffer_of(slot)?;
+        cells[i].as_ref()
+    }
+
+    fn cell_at_mut(&mut self, slot: usize) -> Option<&mut HashCell<K, V>> {
+        match slot.checked_sub(self.cells.len()) {
+            None => self.cells[slot].as_mut(),
+            Some(i) => self.migration.as_mut()?.cells.get_mut(i)?.as_mut(),
+        }
+    }
+
+    // The entry in a slot that is known to be occupied.
+    fn occupied(&self, idx: usize) -> &HashCell<K, V> {
+        self.cell_at(idx).expect("slot is empty")
+    }
+
+    fn occupied_mut(&mut self, idx: usize) -> &mut HashCell<K, V> {
+        self.cell_at_mut(idx).expect("slot is empty")
+    }
+
+    fn first_free_slot(&self, key: &K) -> usize {
+        assert!(self.cells.len() > self.taken_count);
+
+        let mut idx = self.prehash(key) % self.cells.len();
+        while self.cells[idx].is_some() {
+            idx = (idx + 1) % self.cells.len();
+        }
+        idx
+    }
+
+    // The slot an `insert` of `key` would write to right now: its current slot
+    // if it is already present, otherwise the first free slot on its probe
+    // chain. Panics if the table is full, since that insert would resize.
+    pub fn next_free_slot_for(&self, key: &K) -> usize {
+        self.get_index(key)
+            .unwrap_or_else(|| self.first_free_slot(key))
+    }
+
+    // Returns the value for `key`, computing it from the key with `f` if it is
+    // missing. The flag is true when `f` ran and a new entry was created.
+    // Panics if the key is new and the table is a full `fixed_capacity` table.
+    pub fn get_or_insert_with_key<F: FnOnce(&K) -> V>(&mut self, key: K, f: F) -> (&mut V, bool) {
+        let (idx, inserted) = match self.get_index(&key) {
+            Some(idx) => (idx, false),
+            None => {
+                let value = f(&key);
+                (self.insert_new(key, value), true)
+            }
+        };
+        (&mut self.occupied_mut(idx).value, inserted)
+    }
+
+    // The hash the table uses to place `key`. Together with `get_prehashed`
+    // this lets hot loops hash a key once and look it up many times.
+    pub fn prehash(&self, key: &K) -> usize {
+        self.hash_key(key)
+    }
+
+    // Like `std::collections::HashMap`, lookups accept any borrowed form `Q`
+    // of the key, e.g. a `&Path` for `PathBuf` keys. `Q` must hash and compare
+    // exactly like `K`, which the `Hashable` impls for such pairs guarantee.
+    fn hash_key<Q: Hashable + ?Sized>(&self, key: &Q) -> usize {
+        hash_with(&self.hasher, key)
+    }
+
+    // `hash` must come from `prehash` on this table for the same key.
+    pub fn get_prehashed<Q>(&self, hash: usize, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: PartialEq + ?Sized,
+    {
+        self.get_index_prehashed(hash, key)
+            .map(|idx| &self.occupied(idx).value)
+    }
+
+    fn get_index<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hashable + PartialEq + ?Sized,
+    {
+        self.get_index_prehashed(self.hash_key(key), key)
+    }
+
+    fn get_index_prehashed<Q>(&self, hash: usize, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: PartialEq + ?Sized,
+    {
+        find_slot(&self.cells, hash, key).or_else(|| {
+            let migration = self.migration.as_ref()?;
+            find_slot(&migration.cells, hash, key).map(|i| self.cells.len() + i)
+        })
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hashable + PartialEq + ?Sized,
+    {
+        if let Some(idx) = self.get_index(key) {
+            Some(&self.occupied(idx).value)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hashable + PartialEq + ?Sized,
+    {
+        if let Some(idx) = self.get_index(key) {
+            Some(&mut self.occupied_mut(idx).value)
+        } else {
+            None
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hashable + PartialEq + ?Sized,
+    {
+        self.get_index(key).is_some()
+    }
+
+    // True if every key is present. Stops at the first missing one.
+    pub fn contains_all<'a, I>(&self, keys: I) -> bool
+    where
+        I: IntoIterator<Item = &'a K>,
+        K: 'a,
+    {
+        keys.into_iter().all(|key| self.contains_key(key))
+    }
+
+    pub fn get_many<const N: usize>(&self, keys: [&K; N]) -> [Option<&V>; N] {
+        keys.map(|key| self.get(key))
+    }
+
+    // Mutable access to two values at once. If `a == b` the second result is
+    // `None`, since both would alias the same value.
+    pub fn get2_mut(&mut self, a: &K, b: &K) -> (Option<&mut V>, Option<&mut V>) {
+        match (self.get_index(a), self.get_index(b)) {
+            (Some(i), Some(j)) if i != j => {
+                let len = self.cells.len();
+                let old: &mut [_] = match &mut self.migration {
+                    Some(migration) => &mut migration.cells,
+                    None => &mut [],
+                };
+                let (a, b) = match (i.checked_sub(len), j.checked_sub(len)) {
+                    (None, None) => pair_mut(&mut self.cells, i, j),
+                    (Some(i), Some(j)) => pair_mut(old, i, j),
+                    (None, Some(j)) => (&mut self.cells[i], &mut old[j]),
+                    (Some(i), None) => (&mut old[i], &mut self.cells[j]),
+                };
+                let a = &mut a.as_mut().expect("slot is empty").value;
+                let b = &mut b.as_mut().expect("slot is empty").value;
+                (Some(a), Some(b))
+            }
+            (Some(i), _) => (Some(&mut self.occupied_mut(i).value), None),
+            (None, Some(j)) => (None, Some(&mut self.occupied_mut(j).value)),
+            (None, None) => (None, None),
+        }
+    }
+
+    // Applies `f` to the value of `key` in place. Returns false if the key is
+    // not in the table, in which case `f` is not called.
+    pub fn update<F: FnOnce(&mut V)>(&mut self, key: &K, f: F) -> bool {
+        if let Some(value) = self.get_mut(key) {
+            f(value);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Keeps only the entries for which `f` returns true. The survivors are
+    // rehashed into a fresh buffer of the same capacity in a single pass,
+    // which is cheaper than shifting entries back on every removal.
+    pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        self.retain_slots(|_, key, value| f(key, value));
+    }
+
+    // Like `retain`, but `f` also gets the physical slot (see `raw_slots`) the
+    // entry occupies, for policies based on position in the table.
+    pub fn retain_slots<F: FnMut(usize, &K, &mut V) -> bool>(&mut self, f: F) {
+        self.rehash_retaining(self.cells.len(), f);
+        self.maybe_auto_shrink();
+    }
+
+    // Removes the entries at the given physical slots (see `raw_slots`) and
+    // compacts the table in a single rehash. Panics before changing anything
+    // if one of the slots is not occupied.
+    pub fn remove_slots(&mut self, slots: &[usize]) {
+        let mut removed = vec![false; self.slot_count()];
+        for &slot in slots {
+            assert!(
+                self.cell_at(slot).is_some(),
+                "slot {} is not occupied",
+                slot
+            );
+            removed[slot] = true;
+        }
+
+        self.rehash_retaining(self.cells.len(), |i, _, _| !removed[i]);
+        self.maybe_auto_shrink();
+    }
+
+    // Removes the entries for which `f` returns true and hands them back,
+    // unlike `retain`, which drops them. The table is compacted eagerly in one
+    // rehash before this returns, so the extracted entries are already gone
+    // even if the iterator is never consumed.
+    pub fn extract_if<F: FnMut(&K, &mut V) -> bool>(
+        &mut self,
+        mut f: F,
+    ) -> impl Iterator<Item = (K, V)> {
+        let old_taken_count = self.taken_count;
+        let old_cells = self.replace_cells(self.cells.len());
+        let mut extracted = Vec::new();
+
+        for mut cell in old_cells.into_iter().flatten() {
+            if f(&cell.key, &mut cell.value) {
+                extracted.push((cell.key, cell.value));
+            } else {
+                self.place(cell.key, cell.value);
+            }
+        }
+
+        self.ops_since_resize += old_taken_count - self.taken_count;
+        self.maybe_auto_shrink();
+        extracted.into_iter()
+    }
+
+    // Resizes the table to 2 * len + 1 slots and wraps it in a read-only view.
+    // That is compact, yet leaves free slots so that lookups of absent keys
+    // stop early. A full table, even a `fixed_capacity` one, is grown to get
+    // them. Nothing can mutate the view, so it can be shared across threads
+    // by reference without a lock.
+    pub fn freeze(mut self) -> FrozenHashTable<K, V> {
+        self.finish_migration();
+        let capacity = self.roomy_capacity(self.taken_count);
+        if capacity < self.cells.len() || self.is_full() {
+            self.resize(capacity);
+        }
+        FrozenHashTable {
+            table: self.without_hook(),
+        }
+    }
+
+    // The same table, minus its `on_resize` hook.
+    fn without_hook(self) -> HashTable<K, V, NoHook> {
+        HashTable {
+            cells: self.cells,
+            taken_count: self.taken_count,
+            resize_count: self.resize_count,
+            fixed: self.fixed,
+            min_capacity: self.min_capacity,
+            auto_shrink: self.auto_shrink,
+            ops_since_resize: self.ops_since_resize,
+            on_resize: NoHook,
+            next_generation: self.next_generation,
+            hasher: self.hasher,
+            incremental_resize: self.incremental_resize,
+            migration: self.migration,
+        }
+    }
+
+    // Splits the table into the entries for which `f` returns true and the
+    // rest. Both halves are sized as if they were to hold every entry, with
+    // the headroom growth leaves, so neither resizes or ends up full. They
+    // share the table's configuration except for its `on_resize` hook.
+    // Halves of a `fixed_capacity` table keep its capacity.
+    pub fn partition<F: FnMut(&K, &V) -> bool>(self, mut f: F) -> (Self, Self) {
+        let capacity = if self.fixed {
+            self.cells.len()
+        } else {
+            self.roomy_capacity(self.taken_count)
+        };
+        let mut matching = self.empty_like(capacity);
+        let mut rest = self.empty_like(capacity);
+
+        for (key, value) in self {
+            if f(&key, &value) {
+                matching.insert_new(key, value);
+            } else {
+                rest.insert_new(key, value);
+            }
+        }
+
+        (matching, rest)
+    }
+
+    // An empty table of `capacity` slots configured like this one.
+    fn empty_like(&self, capacity: usize) -> Self {
+        Self {
+            fixed: self.fixed,
+            min_capacity: self.min_capacity,
+            auto_shrink: self.auto_shrink,
+            hasher: self.hasher.clone(),
+            incremental_resize: self.incremental_resize,
+            ..Self::empty(capacity)
+        }
+    }
+
+    // Empties the table and yields every entry it held. The table is cleared
+    // up front, so dropping the iterator early still leaves it empty. The
+    // capacity is kept, like `std::collections::HashMap::drain`.
+    pub fn drain(&mut self) -> impl Iterator<Item = (K, V)> {
+        self.drain_to(self.cells.len())
+    }
+
+    // Like `drain`, but also shrinks the table back to `min_capacity`, for
+    // tables that are cycled through large bursts. A `fixed_capacity` table
+    // keeps its capacity.
+    pub fn drain_and_shrink(&mut self) -> impl Iterator<Item = (K, V)> {
+        self.drain_to(self.baseline_capacity())
+    }
+
+    // Drops every entry and reallocates at `min_capacity`, for a table that
+    // ballooned once and should go back to its baseline size. A
+    // `fixed_capacity` table keeps its capacity.
+    pub fn clear_and_reset(&mut self) {
+        self.replace_cells(self.baseline_capacity());
+    }
+
+    fn baseline_capacity(&self) -> usize {
+        if self.fixed {
+            self.cells.len()
+        } else {
+            self.min_capacity
+        }
+    }
+
+    fn drain_to(&mut self, capacity: usize) -> impl Iterator<Item = (K, V)> {
+        let old_cells = self.replace_cells(capacity);
+
+        old_cells.into_iter().flatten().map(|c| (c.key, c.value))
+    }
+
+    // Removes the entry and returns the key as it was stored, which may differ
+    // from `key` when `PartialEq` ignores part of the representation.
+    pub fn take<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hashable + PartialEq + ?Sized,
+    {
+        let taken = self.get_index(key).map(|idx| self.remove_at(idx));
+        if taken.is_some() {
+            self.migrate(MIGRATION_STEP);
+            self.maybe_auto_shrink();
+        }
+        taken
+    }
+
+    fn remove_at(&mut self, idx: usize) -> (K, V) {
+        let cell = match idx.checked_sub(self.cells.len()) {
+            None => {
+                let cell = self.cells[idx].take().expect("slot is empty");
+                shift_back(&mut self.cells, idx, &self.hasher);
+                cell
+            }
+            Some(i) => {
+                let migration = self.migration.as_mut().expect("slot is empty");
+                let cell = migration.cells[i].take().expect("slot is empty");
+                shift_back(&mut migration.cells, i, &self.hasher);
+                migration.remaining -= 1;
+                if migration.remaining == 0 {
+                    self.migration = None;
+                }
+                cell
+            }
+        };
+        self.taken_count -= 1;
+        self.ops_since_resize += 1;
+
+        (cell.key, cell.value)
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let old: &[_] = match &self.migration {
+            Some(migration) => &migration.cells,
+            None => &[],
+        };
+        Iter {
+            cells: self.cells.iter().chain(old).flatten(),
+        }
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { iter: self.iter() }
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { iter: self.iter() }
+    }
+
+    // Applies `f` to each value in slot order, stopping at the first error.
+    // Values after the failing one are left untouched.
+    pub fn try_for_each_value_mut<E, F>(&mut self, f: F) -> Result<(), E>
+    where
+        F: FnMut(&mut V) -> Result<(), E>,
+    {
+        let old: &mut [_] = match &mut self.migration {
+            Some(migration) => &mut migration.cells,
+            None => &mut [],
+        };
+        self.cells
+            .iter_mut()
+            .chain(old)
+            .flatten()
+            .map(|c| &mut c.value)
+            .try_for_each(f)
+    }
+
+    // The first key, in slot order, whose value satisfies `f`. O(n), since
+    // values are not indexed.
+    pub fn find_key<F: FnMut(&V) -> bool>(&self, mut f: F) -> Option<&K> {
+        self.iter().find(|(_, v)| f(v)).map(|(k, _)| k)
+    }
+
+    pub fn fold_values<B, F: FnMut(B, &V) -> B>(&self, init: B, f: F) -> B {
+        self.values().fold(init, f)
+    }
+
+    // Snapshot of every entry as owned pairs, in slot order.
+    pub fn to_vec(&self) -> Vec<(K, V)> {
+        let mut entries = Vec::with_capacity(self.taken_count);
+        entries.extend(self.iter().map(|(k, v)| (k.clone(), v.clone())));
+        entries
+    }
+
+    // Advanced, unstable API: yields every physical slot in slot order, which
+    // exposes the probe layout for tooling. During an incremental resize the
+    // old buffer's slots follow the new one's. The layout may change at any
+    // time.
+    pub fn raw_slots(&self) -> impl Iterator<Item = SlotState<'_, K, V>> {
+        self.buffers().flatten().map(|c| match c {
+            Some(c) => SlotState::Occupied(&c.key, &c.value),
+            None => SlotState::Empty,
+        })
+    }
+
+    // The physical slot `key` occupies, if it is in the table.
+    pub fn slot_of(&self, key: &K) -> Option<usize> {
+        self.get_index(key)
+    }
+
+    pub fn handle(&self, key: &K) -> Option<Handle> {
+        let hash = self.prehash(key);
+        self.get_index_prehashed(hash, key).map(|slot| {
+            let (cells, i) = self.buffer_of(slot).expect("slot out of range");
+            let len = cells.len();
+            Handle {
+                slot,
+                distance_from_home: (i + len - hash % len) % len,
+                generation: self.occupied(slot).generation,
+            }
+        })
+    }
+
+    // Re-reads the entry behind `handle` without hashing. Looks at the slot
+    // the entry had when the handle was taken, then walks back towards its
+    // home slot, so it costs at most the probe length the entry had then.
+    // Returns `None` if the handle went stale (see `Handle`).
+    pub fn get_by_handle(&self, handle: Handle) -> Option<&V> {
+        let (cells, i) = self.buffer_of(handle.slot)?;
+        let len = cells.len();
+
+        (0..=handle.distance_from_home.min(len - 1))
+            .filter_map(|back| cells[(i + len - back) % len].as_ref())
+            .find(|c| c.generation == handle.generation)
+            .map(|c| &c.value)
+    }
+
+    // The value stored in a physical slot, or `None` if the slot is empty or
+    // out of range. Lets a value found through `slot_of`/`raw_slots` be edited
+    // without probing again.
+    pub fn value_at_slot_mut(&mut self, slot: usize) -> Option<&mut V> {
+        self.cell_at_mut(slot).map(|c| &mut c.value)
+    }
+
+    // Debug-only consistency check: the taken count matches the occupied
+    // cells, and every entry can still be found from its home slot (which is
+    // what backward-shift removal and rehashing must preserve).
+    #[cfg(debug_assertions)]
+    pub fn assert_invariants(&self) {
+        assert!(self.taken_count <= self.cells.len());
+        assert_eq!(self.taken_count, self.iter().count());
+        if let Some(migration) = &self.migration {
+            assert_ne!(migration.remaining, 0);
+            assert_eq!(
+                migration.remaining,
+                migration.cells.iter().flatten().count()
+            );
+            assert!(migration.cells[..migration.cursor]
+                .iter()
+                .all(Option::is_none));
+        }
+
+        for (i, c) in self.buffers().flatten().enumerate() {
+            if let Some(c) = c {
+                assert_eq!(
+                    self.get_index(&c.key),
+                    Some(i),
+                    "{:?} is unreachable",
+                    c.key
+                );
+            }
+        }
+    }
+
+    pub fn debug_dump(&self) {
+        println!("----------------------------------------------------------");
+        println!("  Table Len {}", self.cells.len());
+        println!("  Taken Count {}", self.taken_count);
+        if let Some(migration) = &self.migration {
+            println!(
+                "  Migrating {} entries from slot {}",
+                migration.remaining,
+                self.cells.len() + migration.cursor
+            );
+        }
+        println!("  Data");
+        for (i, c) in self.buffers().flatten().enumerate() {
+            match c {
+                Some(c) => println!("    ({})      {:?} => {:?}", i, c.key, c.value),
+                None => println!("    ({})      X", i),
+            }
+        }
+        println!("----------------------------------------------------------");
+    }
+}
+
+#[derive(Debug)]
+pub struct FrozenHashTable<K, V> {
+    table: HashTable<K, V, NoHook>,
+}
+
+impl<K, V> FrozenHashTable<K, V>
+where
+    K: Clone + Hashable + PartialEq + Debug,
+    V: Clone + Debug,
+{
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hashable + PartialEq + ?Sized,
+    {
+        self.table.get(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hashable + PartialEq + ?Sized,
+    {
+        self.table.contains_key(key)
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.table.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+}
+
+// A table shared between threads, split into shards that each sit behind
+// their own `RwLock`. A key's shard is picked from its hash, so readers of
+// different shards never contend and readers of the same shard only wait for
+// writers. `get` returns a clone, since a reference cannot outlive the lock.
+#[derive(Debug)]
+pub struct ConcurrentHashTable<K, V> {
+    shards: Vec<std::sync::RwLock<HashTable<K, V, NoHook>>>,
+}
+
+const DEFAULT_SHARD_COUNT: usize = 16;
+
+impl<K, V> Default for ConcurrentHashTable<K, V>
+where
+    K: Clone + Hashable + PartialEq + Debug,
+    V: Clone + Debug,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> ConcurrentHashTable<K, V>
+where
+    K: Clone + Hashable + PartialEq + Debug,
+    V: Clone + Debug,
+{
+    pub fn new() -> Self {
+        Self::with_shards(DEFAULT_SHARD_COUNT)
+    }
+
+    pub fn with_shards(shard_count: usize) -> Self {
+        assert_ne!(shard_count, 0);
+        Self {
+            shards: (0..shard_count)
+                .map(|_| std::sync::RwLock::new(HashTable::empty(DEFAULT_VEC_CAPACITY)))
+                .collect(),
+        }
+    }
+
+    fn shard(&self, key: &K) -> &std::sync::RwLock<HashTable<K, V, NoHook>> {
+        &self.shards[key.hash() % self.shards.len()]
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.shard(key).read().unwrap().get(key).cloned()
+    }
+
+    pub fn insert(&self, key: K, value: V) -> bool {
+        self.shard(&key).write().unwrap().insert(key, value)
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.shard(key)
+            .write()
+            .unwrap()
+            .take(key)
+            .map(|(_, value)| value)
+    }
+}
+
+// The entries of a table's buffer followed by those of a migration's old
+// buffer, which is empty when there is none.
+type BothBuffers<I> = std::iter::Flatten<std::iter::Chain<I, I>>;
+
+// Entries in slot order. Scanning from the back walks the same slots from
+// the other end, so a mixed forward/backward walk never repeats an entry.
+#[derive(Debug)]
+pub struct Iter<'a, K, V> {
+    cells: BothBuffers<std::slice::Iter<'a, Slot<K, V>>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cells.next().map(|c| (&c.key, &c.value))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.cells.next_back().map(|c| (&c.key, &c.value))
+    }
+}
+
+#[derive(Debug)]
+pub struct Keys<'a, K, V> {
+    iter: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(k, _)| k)
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Keys<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(k, _)| k)
+    }
+}
+
+#[derive(Debug)]
+pub struct Values<'a, K, V> {
+    iter: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(_, v)| v)
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Values<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(_, v)| v)
+    }
+}
+
+#[derive(Debug)]
+pub struct IntoIter<K, V> {
+    cells: BothBuffers<std::vec::IntoIter<Slot<K, V>>>,
+    remaining: usize,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cell = self.cells.next()?;
+        self.remaining -= 1;
+        Some((cell.key, cell.value))
+    }
+
+    // Exact, so collecting into another table or a `HashMap` allocates once.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let cell = self.cells.next_back()?;
+        self.remaining -= 1;
+        Some((cell.key, cell.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl<K, V, H> IntoIterator for HashTable<K, V, H> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            cells: self
+                .cells
+                .into_iter()
+                .chain(self.migration.map_or_else(Vec::new, |m| m.cells))
+                .flatten(),
+            remaining: self.taken_count,
+        }
+    }
+}
+
+// Duplicate keys keep the value that comes last in iteration order, like
+// `std::collections::HashMap`. Resizing mid-collect only moves entries that
+// are already unique, so it cannot change which duplicate wins.
+//
+// The table is sized from the iterator's lower size bound, in the shape
+// growth leaves a table in, so collecting an iterator that reports its length
+// neither resizes nor ends up full.
+impl<K, V> FromIterator<(K, V)> for HashTable<K, V>
+where
+    K: Clone + Hashable + PartialEq + Debug,
+    V: Clone + Debug,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let (lower, _) = iter.size_hint();
+
+        // Starts without slots, so only the final capacity is allocated.
+        let mut table = Self::with_capacity(0);
+        table.reserve_exact(table.roomy_capacity(lower));
+        for (key, value) in iter {
+            table.insert(key, value);
+        }
+        table
+    }
+}
+
+// Reserves room for the iterator's lower size bound up front, with the same
+// headroom as growth leaves, so extending by an iterator that reports its
+// length neither resizes midway nor leaves the table full. Fixed
+// tables are never grown; entries that do not fit are dropped like `insert`.
+// (`Extend::extend_reserve` would be the natural hook, but it is unstable.)
+impl<K, V> Extend<(K, V)> for HashTable<K, V>
+where
+    K: Clone + Hashable + PartialEq + Debug,
+    V: Clone + Debug,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        let (lower, _) = iter.size_hint();
+
+        if !self.fixed {
+            self.reserve_exact(self.roomy_capacity(self.taken_count.saturating_add(lower)));
+        }
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+// `HashMap`'s iterator reports its exact length, so the table is allocated
+// once, big enough for every entry.
+impl<K, V> From<std::collections::HashMap<K, V>> for HashTable<K, V>
+where
+    K: Clone + Hashable + PartialEq + Debug,
+    V: Clone + Debug,
+{
+    fn from(map: std::collections::HashMap<K, V>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<K, V> From<HashTable<K, V>> for std::collections::HashMap<K, V>
+where
+    K: Eq + std::hash::Hash,
+{
+    fn from(table: HashTable<K, V>) -> Self {
+        table.into_iter().collect()
+    }
+}
+
+// Fills a table of `capacity` slots with keys that all share home slot 0,
+// the worst possible clustering for linear probing.
+#[cfg(feature = "testing")]
+pub fn worst_case_fill(capacity: usize) -> HashTable<usize, usize> {
+    let mut table = HashTable::with_capacity(capacity);
+    for i in 0..capacity {
+        table.insert(i * capacity, i);
+    }
+    table
+}
+
+fn benchmark_our_vergin_table(n: usize) {
+    let start = Instant::now();
+
+    let mut table = HashTable::<usize, usize>::new();
+    for _ in 0..n {
+        let n = rand::random::<usize>();
+        if let Some(val) = table.get_mut(&n) {
+            *val += 1; // this might overflow
+        } else {
+            table.insert(n, n);
+        }
+        assert_eq!(*table.get(&n).unwrap(), n);
+    }
+
+    let duration = start.elapsed();
+    println!("Time elapsed in in vergin table is: {:?}", duration);
+}
+
+fn benchmark_std_chad_table(n: usize) {
+    let start = Instant::now();
+
+    let mut table = std::collections::HashMap::<usize, usize>::new();
+    for _ in 0..n {
+        let n = rand::random::<usize>();
+        if let Some(val) = table.get_mut(&n) {
+            *val += 1; // this might overflow
+        } else {
+            table.insert(n, n);
+        }
+        assert_eq!(*table.get(&n).unwrap(), n);
+    }
+
+    let duration = start.elapsed();
+    println!("Time elapsed in in chad table is: {:?}", duration);
+}
+
+// Loads the same common English words into a table per hasher and compares
+// how well each spreads them, from the tables' own `stats`.
+fn benchmark_string_hashers() {
+    let words: Vec<&str> = include_str!("words.txt").split_whitespace().collect();
+
+    let mut djb = HashTable::<&str, ()>::with_hasher(DjbHasher::new());
+    let mut fnv = HashTable::<&str, ()>::with_hasher(Fnv1aHasher::new());
+    for &word in &words {
+        djb.insert(word, ());
+        fnv.insert(word, ());
+    }
+
+    for (name, stats) in [("djb2", djb.stats()), ("fnv1a", fnv.stats())] {
+        println!(
+            "{}: {} words, {} collisions, {:.3} avg probe length, {} max",
+            name, stats.len, stats.collision_count, stats.avg_probe_length, stats.max_probe_length
+        );
+    }
+}
+
+fn main() {
+    let mut table = HashTable::<String, String>::with_capacity(11);
+    for i in 0..11 {
+        table.insert(format!("{}", i), format!("{}", 100_000 + i));
+    }
+
+    // table.debug_dump();
+    assert_eq!(table.capacity(), 11);
+    table.insert("69".to_string(), "69".to_string());
+    // table.debug_dump();
+    assert!(table.capacity() > 11);
+    assert_eq!(table.len(), 12);
+
+    for i in 0..11 {
+        let key = format!("{}", i);
+        assert_eq!(*table.get(&key).unwrap(), format!("{}", 100_000 + i));
+    }
+
+    assert_eq!(*table.get(&("69".to_string())).unwrap(), "69".to_string());
+
+    #[cfg(debug_assertions)]
+    table.assert_invariants();
+
+    let stats = table.stats();
+    assert_eq!((stats.len, stats.capacity), (table.len(), table.capacity()));
+    assert_eq!(stats.load_factor, 12.0 / stats.capacity as f64);
+    assert_eq!(stats.resize_count, 1);
+    assert!(stats.collision_count < stats.len);
+    assert!(
+        1.0 <= stats.avg_probe_length && stats.avg_probe_length <= stats.max_probe_length as f64
+    );
+
+    for i in (0..11).step_by(2) {
+        let key = format!("{}", i);
+        assert_eq!(table.take(&key).unwrap().1, format!("{}", 100_000 + i));
+    }
+    table.retain(|_, v| v.len() > 2);
+
+    #[cfg(debug_assertions)]
+    table.assert_invariants();
+
+    const N: usize = 100_000;
+
+    benchmark_our_vergin_table(N);
     benchmark_std_chad_table(N);
+    benchmark_string_hashers();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash_str_with(mut h: impl StreamingHasher, s: &str) -> usize {
+        h.write(s.as_bytes());
+        h.finish()
+    }
+
+    #[test]
+    fn full_table_grows_and_keeps_every_entry() {
+        let mut table = HashTable::<String, String>::with_capacity(11);
+        for i in 0..11 {
+            table.insert(format!("{}", i), format!("{}", 100_000 + i));
+        }
+        assert_eq!(table.capacity(), 11);
+
+        table.insert("69".to_string(), "69".to_string());
+        assert!(table.capacity() > 11);
+        assert_eq!(table.len(), 12);
+
+        for i in 0..11 {
+            let key = format!("{}", i);
+            assert_eq!(*table.get(&key).unwrap(), format!("{}", 100_000 + i));
+        }
+        assert_eq!(*table.get(&"69".to_string()).unwrap(), "69");
+        #[cfg(debug_assertions)]
+        table.assert_invariants();
+    }
+
+    #[test]
+    fn stats_are_consistent() {
+        let mut table = HashTable::<usize, usize>::with_capacity(11);
+        for i in 0..40 {
+            table.insert(i * 7, i);
+        }
+
+        let stats = table.stats();
+        assert_eq!(stats.len, table.len());
+        assert_eq!(stats.capacity, table.capacity());
+        assert_eq!(stats.load_factor, stats.len as f64 / stats.capacity as f64);
+        assert_eq!(stats.resize_count, table.resize_count());
+        assert!(stats.collision_count <= stats.len);
+        assert!(1.0 <= stats.avg_probe_length);
+        assert!(stats.avg_probe_length <= stats.max_probe_length as f64);
+    }
+
+    #[test]
+    fn rounded_capacity_is_the_next_prime() {
+        for (requested, prime) in [(0, 2), (2, 2), (11, 11), (12, 13), (62, 67), (1000, 1009)] {
+            let (table, capacity) = HashTable::<usize, ()>::with_capacity_rounded(requested);
+            assert_eq!(capacity, prime);
+            assert_eq!(table.capacity(), prime);
+        }
+    }
+
+    #[test]
+    fn estimated_probe_length_stays_finite() {
+        let empty = HashTable::<usize, ()>::with_capacity(0);
+        assert_eq!(empty.estimated_avg_probe_length(), 0.0);
+
+        let mut table = HashTable::<usize, ()>::fixed_capacity(11);
+        assert_eq!(table.estimated_avg_probe_length(), 1.0);
+        for i in 0..11 {
+            table.insert(i, ());
+        }
+        assert_eq!(table.estimated_avg_probe_length(), 11.0);
+    }
+
+    #[test]
+    fn auto_shrink_follows_removals_only_when_enabled() {
+        for auto_shrink in [false, true] {
+            let mut table = HashTable::<usize, usize>::with_capacity(1001);
+            table.set_auto_shrink(auto_shrink);
+            for i in 0..1000 {
+                table.insert(i, i);
+            }
+            table.retain(|k, _| *k < 100);
+            assert_eq!(table.len(), 100);
+
+            let expected = if auto_shrink { 201 } else { 1001 };
+            assert_eq!(table.capacity(), expected);
+            for i in 0..100 {
+                assert_eq!(table.get(&i), Some(&i));
+            }
+        }
+    }
+
+    #[test]
+    fn take_of_a_missing_key_does_not_shrink() {
+        let mut table = HashTable::<usize, usize>::with_capacity(1000);
+        table.set_auto_shrink(true);
+        for i in 0..100 {
+            table.insert(i, i);
+        }
+
+        assert_eq!(table.take(&5000), None);
+        assert_eq!(table.capacity(), 1000);
+
+        assert_eq!(table.take(&0), Some((0, 0)));
+        assert_eq!(table.capacity(), 199);
+    }
+
+    #[test]
+    fn clear_and_reset_returns_to_the_floor() {
+        let mut table = HashTable::<usize, usize>::new();
+        for i in 0..1000 {
+            table.insert(i, i);
+        }
+        assert!(table.capacity() > DEFAULT_VEC_CAPACITY);
+
+        table.clear_and_reset();
+        assert!(table.is_empty());
+        assert_eq!(table.capacity(), DEFAULT_VEC_CAPACITY);
+
+        table.set_min_capacity(7);
+        table.extend((0..100).map(|i| (i, i)));
+        assert_eq!(table.drain_and_shrink().count(), 100);
+        assert_eq!(table.capacity(), 7);
+    }
+
+    #[test]
+    fn resetting_keeps_a_fixed_capacity() {
+        let mut table = HashTable::<usize, usize>::fixed_capacity(1000);
+        table.insert(1, 1);
+        table.clear_and_reset();
+        assert_eq!(table.capacity(), 1000);
+        assert_eq!((0..1000).filter(|&i| table.insert(i, i)).count(), 1000);
+
+        let mut table = HashTable::<usize, usize>::fixed_capacity(10);
+        table.insert(1, 1);
+        assert_eq!(table.drain_and_shrink().collect::<Vec<_>>(), [(1, 1)]);
+        assert_eq!(table.capacity(), 10);
+    }
+
+    #[test]
+    fn shrink_to_fit_undoes_an_over_reservation() {
+        let mut table = HashTable::<usize, usize>::new();
+        table.reserve_exact(1_000_000);
+        assert_eq!(table.capacity(), 1_000_000);
+
+        for i in 0..100 {
+            table.insert(i, i);
+        }
+        table.shrink_to_fit();
+        assert_eq!(table.capacity(), 201);
+        assert!(!table.is_full());
+        for i in 0..100 {
+            assert_eq!(table.get(&i), Some(&i));
+        }
+
+        table.retain(|_, _| false);
+        table.shrink_to_fit();
+        assert_eq!(table.capacity(), DEFAULT_VEC_CAPACITY);
+    }
+
+    #[test]
+    fn reserving_and_shrinking_leave_fixed_tables_alone() {
+        let mut table = HashTable::<usize, usize>::fixed_capacity(500);
+        table.insert(1, 1);
+        table.shrink_to_fit();
+        assert_eq!(table.capacity(), 500);
+
+        let mut table = HashTable::<usize, usize>::fixed_capacity(10);
+        table.reserve_exact(100);
+        assert_eq!(table.capacity(), 10);
+    }
+
+    #[test]
+    fn frozen_table_is_roomy_and_shared_across_threads() {
+        let mut table = HashTable::<usize, usize>::with_capacity(1000);
+        for i in 0..100 {
+            table.insert(i, i * i);
+        }
+        let frozen = table.freeze();
+        assert_eq!(frozen.len(), 100);
+        assert_eq!(frozen.table.capacity(), 201);
+
+        std::thread::scope(|s| {
+            for t in 0..4 {
+                let frozen = &frozen;
+                s.spawn(move || {
+                    for i in 0..200 {
+                        let expected = (i < 100).then_some(i * i);
+                        assert_eq!(frozen.get(&i).copied(), expected, "thread {}", t);
+                        assert_eq!(frozen.contains_key(&i), i < 100);
+                    }
+                    assert_eq!(frozen.iter().count(), 100);
+                });
+            }
+        });
+
+        let mut full = HashTable::<usize, usize>::fixed_capacity(10);
+        for i in 0..10 {
+            full.insert(i, i);
+        }
+        let frozen = full.freeze();
+        assert!(!frozen.table.is_full());
+        assert!(!frozen.contains_key(&10));
+    }
+
+    #[test]
+    fn partition_splits_by_parity_and_keeps_configuration() {
+        let mut table = HashTable::<usize, usize>::with_domain(b"ids");
+        table.set_min_capacity(7);
+        table.set_auto_shrink(true);
+        for i in 0..100 {
+            table.insert(i, i);
+        }
+        let mut original = table.to_vec();
+        original.sort();
+
+        let (even, odd) = table.partition(|_, v| v % 2 == 0);
+        assert_eq!((even.len(), odd.len()), (50, 50));
+        assert!(even.values().all(|v| v % 2 == 0));
+        assert!(odd.values().all(|v| v % 2 == 1));
+        for half in [&even, &odd] {
+            assert_eq!(half.capacity(), 201);
+            assert_eq!(half.min_capacity(), 7);
+            assert!(half.auto_shrink);
+            assert_eq!(
+                half.prehash(&3),
+                HashTable::<usize, ()>::with_domain(b"ids").prehash(&3)
+            );
+        }
+
+        let mut rebuilt: Vec<_> = even.into_iter().chain(odd).collect();
+        rebuilt.sort();
+        assert_eq!(rebuilt, original);
+
+        let lopsided: HashTable<usize, usize> = (0..100).map(|i| (i, i)).collect();
+        let (all, none) = lopsided.partition(|_, _| true);
+        assert_eq!((all.len(), none.len()), (100, 0));
+        assert!(!all.is_full());
+
+        let mut fixed = HashTable::<usize, usize>::fixed_capacity(10);
+        for i in 0..10 {
+            fixed.insert(i, i);
+        }
+        let (low, high) = fixed.partition(|k, _| *k < 3);
+        assert_eq!((low.capacity(), high.capacity()), (10, 10));
+        assert!(low.fixed && high.fixed);
+    }
+
+    #[test]
+    fn path_keys_follow_path_equality() {
+        let mut table = HashTable::<PathBuf, usize>::new();
+        table.insert(PathBuf::from("a/b"), 1);
+        table.insert(PathBuf::from("/etc/hosts"), 2);
+
+        assert_eq!(PathBuf::from("a/b"), PathBuf::from("a//b/"));
+        assert_eq!(PathBuf::from("a/b").hash(), PathBuf::from("a//b/").hash());
+        assert_eq!(table.get(&PathBuf::from("a//b/")), Some(&1));
+        table.insert(PathBuf::from("a//b/"), 3);
+        assert_eq!(table.len(), 2);
+
+        assert_eq!(table.get(Path::new("a/b")), Some(&3));
+        assert_eq!(table.get(Path::new("/etc//hosts")), Some(&2));
+        assert!(!table.contains_key(Path::new("ab")));
+        assert_eq!(
+            table.take(Path::new("/etc/hosts")),
+            Some(("/etc/hosts".into(), 2))
+        );
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn boxed_dyn_hashable_hashes_like_its_value() {
+        let strategies: Vec<Box<dyn DynHashable>> = vec![
+            Box::new("key".to_string()),
+            Box::new(7usize),
+            Box::new(vec![1u32, 2]),
+        ];
+        let expected = [
+            "key".to_string().hash(),
+            7usize.hash(),
+            [1u32, 2][..].hash(),
+        ];
+        for (boxed, expected) in strategies.iter().zip(expected) {
+            assert_eq!(boxed.hash(), expected);
+        }
+
+        // A hash chosen at runtime can drive a lookup of an untagged table.
+        let mut table = HashTable::<String, usize>::new();
+        table.insert("key".to_string(), 1);
+        let hash = strategies[0].hash();
+        assert_eq!(table.get_prehashed(hash, "key"), Some(&1));
+    }
+
+    #[test]
+    fn resize_hook_reports_capacities_through_an_rc_counter() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let count = Rc::new(Cell::new(0));
+        let last = Rc::new(Cell::new((0, 0)));
+        let mut table = HashTable::<usize, usize>::with_capacity(5);
+        let (hook_count, hook_last) = (Rc::clone(&count), Rc::clone(&last));
+        table.on_resize(move |old, new| {
+            hook_count.set(hook_count.get() + 1);
+            hook_last.set((old, new));
+        });
+
+        for i in 0..6 {
+            table.insert(i, i);
+        }
+        assert_eq!((count.get(), last.get()), (1, (5, 11)));
+
+        table.reserve_exact(100);
+        assert_eq!((count.get(), last.get()), (2, (11, 100)));
+    }
+
+    #[test]
+    fn shared_tables_are_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<FrozenHashTable<String, usize>>();
+        assert_send_sync::<ConcurrentHashTable<String, usize>>();
+    }
+
+    #[test]
+    fn nonzero_integers_are_keys() {
+        let mut table = HashTable::<NonZeroU64, &str>::new();
+        let one = NonZeroU64::new(1).unwrap();
+        let big = NonZeroU64::new(u64::MAX).unwrap();
+        table.insert(one, "one");
+        table.insert(big, "max");
+        assert_eq!(table.get(&one), Some(&"one"));
+        assert_eq!(table.get(&big), Some(&"max"));
+        assert_eq!(table.get(&NonZeroU64::new(2).unwrap()), None);
+    }
+
+    #[test]
+    fn ip_and_socket_addresses_are_keys() {
+        let v4: IpAddr = Ipv4Addr::new(10, 0, 0, 1).into();
+        let v6: IpAddr = Ipv6Addr::LOCALHOST.into();
+        let mut routes = HashTable::<IpAddr, &str>::new();
+        routes.insert(v4, "lan");
+        routes.insert(v6, "loopback");
+        assert_eq!(routes.get(&v4), Some(&"lan"));
+        assert_eq!(routes.get(&v6), Some(&"loopback"));
+        assert!(!routes.contains_key(&IpAddr::from(Ipv4Addr::LOCALHOST)));
+
+        let mut limits = HashTable::<SocketAddr, usize>::new();
+        limits.insert(SocketAddr::new(v4, 80), 1);
+        limits.insert(SocketAddr::new(v4, 443), 2);
+        limits.insert(SocketAddr::new(v6, 80), 3);
+        assert_eq!(limits.len(), 3);
+        assert_eq!(limits.get(&SocketAddr::new(v4, 443)), Some(&2));
+        assert_eq!(limits.get(&SocketAddr::new(v6, 80)), Some(&3));
+    }
+
+    #[test]
+    fn durations_and_system_times_are_keys() {
+        let mut buckets = HashTable::<Duration, usize>::new();
+        for secs in 0..10 {
+            buckets.insert(Duration::from_secs(secs), secs as usize);
+        }
+        buckets.insert(Duration::new(1, 1), 100);
+        assert_eq!(buckets.len(), 11);
+        assert_eq!(buckets.get(&Duration::from_millis(3000)), Some(&3));
+        assert_eq!(buckets.get(&Duration::new(1, 1)), Some(&100));
+
+        let before = UNIX_EPOCH - Duration::from_secs(60);
+        let after = UNIX_EPOCH + Duration::from_secs(60);
+        let mut seen = HashTable::<SystemTime, &str>::new();
+        seen.insert(before, "before");
+        seen.insert(after, "after");
+        seen.insert(UNIX_EPOCH, "epoch");
+        assert_eq!(seen.get(&before), Some(&"before"));
+        assert_eq!(seen.get(&after), Some(&"after"));
+        assert_eq!(seen.get(&UNIX_EPOCH), Some(&"epoch"));
+    }
+
+    #[test]
+    fn handle_reads_its_entry_while_the_table_keeps_its_shape() {
+        let mut table = HashTable::<usize, usize>::with_capacity(11);
+        for i in 0..5 {
+            table.insert(i, i * 10);
+        }
+        let handle = table.handle(&3).unwrap();
+        assert_eq!(table.get_by_handle(handle), Some(&30));
+
+        table.insert(7, 70);
+        table.take(&1);
+        assert_eq!(table.get_by_handle(handle), Some(&30));
+        assert_eq!(table.handle(&99), None);
+    }
+
+    #[test]
+    fn handle_follows_an_entry_shifted_back_by_a_removal() {
+        // 0 and 11 share home slot 0 in 11 slots, so 11 sits in slot 1 until
+        // removing 0 shifts it back.
+        let mut table = HashTable::<usize, usize>::with_capacity(11);
+        table.insert(0, 0);
+        table.insert(11, 110);
+        let handle = table.handle(&11).unwrap();
+        assert_eq!(table.slot_of(&11), Some(1));
+
+        table.take(&0);
+        assert_eq!(table.slot_of(&11), Some(0));
+        assert_eq!(table.get_by_handle(handle), Some(&110));
+        assert_eq!(table.get(&11), Some(&110));
+    }
+
+    #[test]
+    fn handle_goes_stale_on_resize() {
+        let mut table = HashTable::<usize, usize>::with_capacity(11);
+        table.insert(3, 30);
+        let handle = table.handle(&3).unwrap();
+
+        for i in 100..120 {
+            table.insert(i, i);
+        }
+        assert!(table.capacity() > 11);
+        assert_eq!(table.get_by_handle(handle), None);
+        assert_eq!(table.get(&3), Some(&30));
+
+        let mut table = HashTable::<usize, usize>::with_capacity(11);
+        table.set_incremental_resize(true);
+        table.insert(3, 30);
+        let handle = table.handle(&3).unwrap();
+        table.reserve_exact(23);
+        assert!(table.is_migrating());
+        assert_eq!(table.get_by_handle(handle), None);
+        assert_eq!(table.get(&3), Some(&30));
+    }
+
+    #[test]
+    fn handle_goes_stale_on_remove_and_reinsert() {
+        let mut table = HashTable::<usize, usize>::with_capacity(11);
+        table.insert(3, 30);
+        let handle = table.handle(&3).unwrap();
+
+        table.take(&3);
+        assert_eq!(table.get_by_handle(handle), None);
+        table.insert(3, 31);
+        assert_eq!(table.slot_of(&3), Some(3));
+        assert_eq!(table.get_by_handle(handle), None);
+
+        let fresh = table.handle(&3).unwrap();
+        assert_eq!(table.get_by_handle(fresh), Some(&31));
+    }
+
+    #[test]
+    fn byte_slices_hash_through_djb_hasher() {
+        let mut h = DjbHasher::new();
+        h.write(b"abc");
+        h.write_word(3);
+        assert_eq!(b"abc"[..].hash(), h.finish());
+        assert_eq!("abc".hash(), h.finish());
+
+        // The classic recurrence, spelled out: hash * 33 + c from 5381.
+        let classic = b"abc"
+            .iter()
+            .map(|&c| c as usize)
+            .chain([3])
+            .fold(5381usize, |hash, x| hash.wrapping_mul(33).wrapping_add(x));
+        assert_eq!("abc".hash(), classic);
+    }
+
+    #[test]
+    fn djb_parameters_change_the_hash() {
+        let classic = hash_str_with(DjbHasher::new(), "hello");
+        let tuned = hash_str_with(DjbHasher::with_params(0, 7), "hello");
+        assert_ne!(classic, tuned);
+        assert_eq!(
+            classic,
+            hash_str_with(DjbHasher::with_params(5381, 5), "hello")
+        );
+    }
+
+    #[test]
+    fn fnv_table_matches_std_hash_map() {
+        let mut table = HashTable::<String, usize>::with_hasher(Fnv1aHasher::new());
+        let mut map = std::collections::HashMap::new();
+        for i in 0..5000 {
+            let key = format!("word{}", i % 700);
+            if i % 3 == 0 {
+                assert_eq!(table.take(&key).map(|(_, v)| v), map.remove(&key));
+            } else {
+                table.insert(key.clone(), i);
+                map.insert(key, i);
+            }
+        }
+
+        assert_eq!(table.len(), map.len());
+        for (key, value) in &map {
+            assert_eq!(table.get(key), Some(value));
+            assert_eq!(table.get(key.as_str()), Some(value));
+        }
+        #[cfg(debug_assertions)]
+        table.assert_invariants();
+
+        let mut fnv = Fnv1aHasher::new();
+        "word1".hash_into(&mut fnv);
+        assert_eq!(table.prehash(&"word1".to_string()), fnv.finish());
+    }
+
+    #[test]
+    fn djb_parameters_reach_a_table() {
+        let key = "hello".to_string();
+        let mut tuned = HashTable::<String, ()>::with_hasher(DjbHasher::with_params(0, 7));
+        let classic = HashTable::<String, ()>::with_hasher(DjbHasher::new());
+        assert_ne!(tuned.prehash(&key), classic.prehash(&key));
+
+        for i in 0..100 {
+            tuned.insert(format!("{}", i), ());
+        }
+        assert!((0..100).all(|i| tuned.contains_key(&format!("{}", i))));
+    }
+
+    #[test]
+    fn incremental_resize_serves_both_buffers() {
+        let mut table = HashTable::<usize, usize>::with_capacity(101);
+        table.set_incremental_resize(true);
+        let mut map = std::collections::HashMap::new();
+        for i in 0..102 {
+            table.insert(i, i);
+            map.insert(i, i);
+        }
+        assert!(table.is_migrating());
+        assert_eq!(table.capacity(), 203);
+
+        // Old keys are spread over both buffers now; touch every kind of op.
+        for i in 0..60 {
+            if i % 3 == 0 {
+                assert_eq!(table.take(&i), map.remove(&i).map(|v| (i, v)));
+            } else {
+                table.insert(i, i * 10);
+                map.insert(i, i * 10);
+            }
+            table.insert(1000 + i, i);
+            map.insert(1000 + i, i);
+            let (a, b) = table.get2_mut(&61, &101);
+            *a.unwrap() += 1;
+            *b.unwrap() += 1;
+            *map.get_mut(&61).unwrap() += 1;
+            *map.get_mut(&101).unwrap() += 1;
+
+            for key in (0..102).chain(1000..1060) {
+                assert_eq!(table.get(&key), map.get(&key));
+            }
+            #[cfg(debug_assertions)]
+            table.assert_invariants();
+        }
+
+        assert_eq!(table.len(), map.len());
+        let mut entries: Vec<_> = table.into_iter().collect();
+        let mut expected: Vec<_> = map.into_iter().collect();
+        entries.sort();
+        expected.sort();
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn incremental_resize_finishes() {
+        let mut table = HashTable::<usize, ()>::with_capacity(61);
+        table.set_incremental_resize(true);
+        for i in 0..62 {
+            table.insert(i, ());
+        }
+        assert!(table.is_migrating());
+
+        // Far fewer operations than fill the new buffer, removals included.
+        for i in 0..20 {
+            table.take(&i);
+            table.insert(100 + i, ());
+        }
+        assert!(!table.is_migrating());
+        assert_eq!(table.len(), 62);
+        assert!((20..62).chain(100..120).all(|i| table.contains_key(&i)));
+
+        // Turning the mode off finishes a migration on the spot.
+        table.reserve_exact(1000);
+        assert!(table.is_migrating());
+        table.set_incremental_resize(false);
+        assert!(!table.is_migrating());
+        assert_eq!(table.capacity(), 1000);
+        #[cfg(debug_assertions)]
+        table.assert_invariants();
+    }
+
+    // Compares by `id` alone, so two labels can share a slot.
+    #[derive(Debug, Clone)]
+    struct Labelled {
+        id: usize,
+        label: &'static str,
+    }
+
+    impl PartialEq for Labelled {
+        fn eq(&self, other: &Self) -> bool {
+            self.id == other.id
+        }
+    }
+
+    impl Hashable for Labelled {
+        fn hash(&self) -> usize {
+            self.id.hash()
+        }
+    }
+
+    #[test]
+    fn replace_swaps_in_the_new_key() {
+        let old = Labelled {
+            id: 7,
+            label: "old",
+        };
+        let new = Labelled {
+            id: 7,
+            label: "new",
+        };
+        let mut table = HashTable::new();
+        assert_eq!(table.replace(old, 1), None);
+
+        let (key, value) = table.replace(new.clone(), 2).unwrap();
+        assert_eq!((key.label, value), ("old", 1));
+        assert_eq!(table.keys().next().unwrap().label, "new");
+        assert_eq!(table.get(&new), Some(&2));
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "fixed-capacity table is full")]
+    fn replace_of_a_new_key_panics_on_a_full_fixed_table() {
+        let mut table = HashTable::<usize, usize>::fixed_capacity(1);
+        table.insert(1, 1);
+        table.replace(2, 2);
+    }
+
+    #[test]
+    fn full_fixed_table_rejects_new_keys_but_takes_updates() {
+        let mut table = HashTable::<usize, usize>::fixed_capacity(10);
+        for i in 0..10 {
+            assert!(table.insert(i, i));
+        }
+        assert!(table.is_full());
+
+        assert!(!table.insert(10, 10));
+        assert!(!table.insert_if_absent(11, 11));
+        assert!(!table.contains_key(&10));
+
+        assert!(table.insert(3, 30));
+        assert_eq!(table.get(&3), Some(&30));
+        assert_eq!((table.len(), table.capacity()), (10, 10));
+    }
+
+    #[test]
+    fn tables_without_slots_work() {
+        let mut fixed = HashTable::<usize, usize>::fixed_capacity(0);
+        assert_eq!(fixed.get(&1), None);
+        assert!(!fixed.insert(1, 1));
+        assert_eq!(fixed.take(&1), None);
+        assert!(fixed.is_empty());
+
+        let mut growable = HashTable::<usize, usize>::with_capacity(0);
+        assert_eq!(growable.get(&1), None);
+        assert!(growable.insert(1, 1));
+        assert_eq!(growable.get(&1), Some(&1));
+        assert_eq!(growable.capacity(), 1);
+    }
+
+    #[test]
+    fn collecting_a_sized_iterator_never_resizes() {
+        let table: HashTable<usize, usize> = (0..100_000).map(|i| (i, i)).collect();
+        assert_eq!(table.resize_count(), 0);
+        assert_eq!(table.len(), 100_000);
+        assert!(!table.is_full());
+        assert!(table.capacity() > 100_000);
+    }
+
+    #[test]
+    fn extending_by_a_sized_iterator_never_resizes() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let mut table = HashTable::<usize, usize>::new();
+        let resizes = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&resizes);
+        table.on_resize(move |_, _| counter.set(counter.get() + 1));
+
+        table.extend((0..20_000).map(|i| (i, i)));
+        assert_eq!(table.resize_count(), 0);
+        assert_eq!(resizes.get(), 1);
+        assert!(!table.is_full());
+
+        table.extend((20_000..30_000).map(|i| (i, i)));
+        assert_eq!(table.resize_count(), 0);
+        assert_eq!(table.len(), 30_000);
+        assert!(!table.is_full());
+    }
+
+    #[test]
+    fn concurrent_table_stays_consistent_under_readers_and_writers() {
+        const KEYS: usize = 1000;
+        const WRITERS: usize = 2;
+        const ROUNDS: usize = 50;
+
+        let table = ConcurrentHashTable::<usize, usize>::with_shards(8);
+        for k in 0..KEYS {
+            table.insert(k, k);
+        }
+
+        // Values are always `k + KEYS * round`, so a reader can tell a torn
+        // or misplaced value from a current one.
+        std::thread::scope(|s| {
+            for w in 0..WRITERS {
+                let table = &table;
+                s.spawn(move || {
+                    for round in 1..=ROUNDS {
+                        for k in (w..KEYS).step_by(WRITERS) {
+                            if k % 10 == round % 10 {
+                                assert_eq!(table.remove(&k).map(|v| v % KEYS), Some(k));
+                            }
+                            assert!(table.insert(k, k + KEYS * round));
+                        }
+                    }
+                });
+            }
+            for _ in 0..8 {
+                let table = &table;
+                s.spawn(move || {
+                    for _ in 0..20 {
+                        for k in 0..KEYS {
+                            if let Some(v) = table.get(&k) {
+                                assert_eq!(v % KEYS, k);
+                                assert!(v / KEYS <= ROUNDS);
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        for k in 0..KEYS {
+            assert_eq!(table.get(&k), Some(k + KEYS * ROUNDS));
+        }
+        assert_eq!(table.get(&KEYS), None);
+    }
+
+    #[test]
+    fn errors_format_and_are_std_errors() {
+        fn message(e: &dyn std::error::Error) -> String {
+            e.to_string()
+        }
+
+        assert_eq!(
+            message(&InsertError::TableFull { capacity: 3 }),
+            "fixed-capacity table is full (3 slots)"
+        );
+        assert_eq!(
+            message(&ReserveError::CapacityOverflow),
+            "capacity overflow"
+        );
+        assert_eq!(
+            message(&ReserveError::FixedCapacity { capacity: 3 }),
+            "fixed-capacity table cannot grow past 3 slots"
+        );
+    }
+
+    #[test]
+    fn try_insert_reports_a_full_fixed_table() {
+        let mut table = HashTable::<usize, usize>::fixed_capacity(2);
+        assert_eq!(table.try_insert(1, 1), Ok(()));
+        assert_eq!(table.try_insert(2, 2), Ok(()));
+        assert_eq!(table.try_insert(1, 10), Ok(()));
+        assert_eq!(
+            table.try_insert(3, 3),
+            Err(InsertError::TableFull { capacity: 2 })
+        );
+        assert_eq!(table.get(&1), Some(&10));
+    }
+
+    #[test]
+    fn try_reserve_reports_overflow_and_fixed_tables() {
+        let mut table = HashTable::<usize, usize>::new();
+        table.insert(1, 1);
+        assert_eq!(
+            table.try_reserve(usize::MAX),
+            Err(ReserveError::CapacityOverflow)
+        );
+        assert_eq!(
+            table.try_reserve(usize::MAX / 4),
+            Err(ReserveError::CapacityOverflow)
+        );
+        assert_eq!(table.capacity(), DEFAULT_VEC_CAPACITY);
+
+        assert_eq!(table.try_reserve(1000), Ok(()));
+        assert_eq!(table.capacity(), 2003);
+
+        let mut fixed = HashTable::<usize, usize>::fixed_capacity(10);
+        assert_eq!(fixed.try_reserve(10), Ok(()));
+        assert_eq!(
+            fixed.try_reserve(11),
+            Err(ReserveError::FixedCapacity { capacity: 10 })
+        );
+    }
+
+    #[test]
+    fn strings_hash_by_content_like_str_and_bytes() {
+        let mut roomy = String::with_capacity(64);
+        roomy.push_str("abc");
+        let shrunk = {
+            let mut s = String::from("abcdef");
+            s.truncate(3);
+            s.shrink_to_fit();
+            s
+        };
+
+        for s in [&"abc".to_string(), &roomy, &shrunk] {
+            assert_eq!(s.hash(), "abc".hash());
+        }
+        assert_eq!("abc".hash(), "abc".as_bytes().hash());
+
+        let mut h = DjbHasher::new();
+        roomy.hash_into(&mut h);
+        let mut g = DjbHasher::new();
+        "abc".hash_into(&mut g);
+        assert_eq!(h.finish(), g.finish());
+    }
+
+    #[test]
+    fn domain_tags_separate_hashes_and_tagged_tables_work() {
+        let mut users = HashTable::<String, usize>::with_domain(b"users");
+        let groups = HashTable::<String, usize>::with_domain(b"groups");
+        let key = "alice".to_string();
+        assert_ne!(users.prehash(&key), groups.prehash(&key));
+        assert_eq!(
+            users.prehash(&key),
+            HashTable::<String, usize>::with_domain(b"users").prehash(&key)
+        );
+
+        for i in 0..500 {
+            users.insert(format!("user{}", i), i);
+        }
+        for i in 0..500 {
+            assert_eq!(users.get(format!("user{}", i).as_str()), Some(&i));
+        }
+        assert_eq!(users.take("user7"), Some(("user7".to_string(), 7)));
+        assert!(!users.contains_key("user7"));
+        #[cfg(debug_assertions)]
+        users.assert_invariants();
+    }
+
+    #[test]
+    fn collecting_duplicate_keys_keeps_the_last_value_across_resizes() {
+        // `filter` reports a lower bound of 0, so the table starts without
+        // slots and has to grow between the duplicates.
+        let others = |range: std::ops::Range<usize>| range.map(|i| (format!("k{}", i), i));
+        let entries = std::iter::once(("a".to_string(), 1))
+            .chain(others(0..50))
+            .chain(std::iter::once(("a".to_string(), 2)))
+            .chain(others(50..100))
+            .chain(std::iter::once(("a".to_string(), 3)))
+            .filter(|_| true);
+
+        let table: HashTable<String, usize> = entries.collect();
+        assert!(table.resize_count() > 0);
+        assert_eq!(table.len(), 101);
+        assert_eq!(table.get("a"), Some(&3));
+        #[cfg(debug_assertions)]
+        table.assert_invariants();
+    }
+
+    #[test]
+    fn estimated_probe_length_tracks_the_measured_one_on_random_keys() {
+        let mut table = HashTable::<usize, ()>::fixed_capacity(10_007);
+        while table.len() < 5_000 {
+            table.insert(rand::random::<usize>(), ());
+        }
+
+        let estimated = table.estimated_avg_probe_length();
+        let measured = table.stats().avg_probe_length;
+        assert!(
+            (estimated - measured).abs() < 0.25,
+            "estimated {} vs measured {}",
+            estimated,
+            measured
+        );
+    }
+
+    #[test]
+    fn oscillating_at_the_shrink_boundary_does_not_thrash() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let mut table = HashTable::<usize, usize>::with_capacity(1001);
+        table.set_auto_shrink(true);
+        for i in 0..249 {
+            table.insert(i, i);
+        }
+        let resizes = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&resizes);
+        table.on_resize(move |_, _| counter.set(counter.get() + 1));
+
+        // The first `take` drops the table just under a quarter full, which
+        // shrinks it to about half full, clear of both boundaries.
+        for _ in 0..1000 {
+            table.insert(1000, 1000);
+            table.take(&1000);
+        }
+        assert_eq!(resizes.get(), 1);
+        assert_eq!(table.capacity(), 499);
+        assert_eq!(table.len(), 249);
+    }
+
+    #[test]
+    fn capacity_math_saturates_near_usize_max() {
+        assert_eq!(grown_capacity(10), 21);
+        assert_eq!(grown_capacity(usize::MAX / 2 - 1), usize::MAX - 2);
+
+        let table = HashTable::<usize, usize>::new();
+        assert_eq!(table.roomy_capacity(usize::MAX / 2), usize::MAX);
+        assert_eq!(table.roomy_capacity(usize::MAX), usize::MAX);
+
+        // An iterator that claims far more entries than it yields. A fixed
+        // table never reserves for it, so it just takes what fits.
+        struct Boastful(std::ops::Range<usize>);
+        impl Iterator for Boastful {
+            type Item = (usize, usize);
+            fn next(&mut self) -> Option<Self::Item> {
+                self.0.next().map(|i| (i, i))
+            }
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (usize::MAX, None)
+            }
+        }
+        let mut fixed = HashTable::<usize, usize>::fixed_capacity(10);
+        fixed.insert(0, 0);
+        fixed.extend(Boastful(0..20));
+        assert_eq!((fixed.len(), fixed.capacity()), (10, 10));
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity overflow")]
+    fn growing_past_usize_max_panics() {
+        grown_capacity(usize::MAX / 2 + 1);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn u128_high_bits_reach_the_hash() {
+        let low = 0x1234_5678_9abc_def0u128;
+        let mut hashes: Vec<usize> = (64..128).map(|bit| (low | 1 << bit).hash()).collect();
+        hashes.push(low.hash());
+        hashes.sort_unstable();
+        hashes.dedup();
+        assert_eq!(hashes.len(), 65);
+
+        assert_ne!((u128::MAX << 64).hash(), 0u128.hash());
+        assert_ne!((-1i128 << 64).hash(), 0i128.hash());
+    }
+
+    #[test]
+    fn nan_keys_are_found_again() {
+        let quiet = TotalF64(f64::NAN);
+        let other_nan = TotalF64(f64::from_bits(f64::NAN.to_bits() | 1));
+        assert!(other_nan.0.is_nan());
+        assert_eq!(quiet, other_nan);
+        assert_eq!(quiet.hash(), other_nan.hash());
+
+        let mut table = HashTable::<TotalF64, &str>::new();
+        table.insert(quiet, "nan");
+        table.insert(TotalF64(1.5), "one and a half");
+        assert_eq!(table.get(&other_nan), Some(&"nan"));
+        assert_eq!(table.get(&TotalF64(1.5)), Some(&"one and a half"));
+
+        table.insert(other_nan, "still nan");
+        assert_eq!(table.len(), 2);
+        let (key, value) = table.take(&quiet).unwrap();
+        assert!(key.0.is_nan());
+        assert_eq!(value, "still nan");
+        assert_eq!(table.get(&quiet), None);
+    }
+
+    #[test]
+    fn slice_hashes_fold_in_the_length() {
+        // The element fold alone is `h = 33 * h + x` from 5381, so one
+        // element equal to 33 * 32 * 5381 lands where two zeros do.
+        let fold_elements = |xs: &[usize]| {
+            let mut h = DjbHasher::new();
+            for &x in xs {
+                h.write_word(x.hash());
+            }
+            h.finish()
+        };
+        let one: &[usize] = &[33 * 32 * 5381];
+        let two: &[usize] = &[0, 0];
+        assert_eq!(fold_elements(one), fold_elements(two));
+
+        assert_ne!(one.hash(), two.hash());
+    }
+
+    #[test]
+    #[cfg(feature = "testing")]
+    fn worst_case_fill_keeps_every_key_retrievable() {
+        let table = worst_case_fill(101);
+        assert!(table.is_full());
+        for i in 0..101 {
+            assert_eq!(table.get(&(i * 101)), Some(&i));
+        }
+        assert_eq!(table.stats().max_probe_length, 101);
+        #[cfg(debug_assertions)]
+        table.assert_invariants();
+    }
+
+    #[test]
+    fn iterators_run_backwards_and_meet_in_the_middle() {
+        let mut table = HashTable::<usize, usize>::new();
+        table.set_incremental_resize(true);
+        // One past the default capacity starts a migration, so the walks
+        // below cross both buffers.
+        for i in 0..62 {
+            table.insert(i, i * 10);
+        }
+        assert!(table.is_migrating());
+
+        let forward: Vec<_> = table.iter().collect();
+        let mut backward: Vec<_> = table.iter().rev().collect();
+        backward.reverse();
+        assert_eq!(forward, backward);
+
+        let mut keys: Vec<_> = table.keys().rev().collect();
+        keys.reverse();
+        assert_eq!(keys, table.keys().collect::<Vec<_>>());
+        let mut values: Vec<_> = table.values().rev().collect();
+        values.reverse();
+        assert_eq!(values, table.values().collect::<Vec<_>>());
+
+        let mut iter = table.iter();
+        let mut seen = Vec::new();
+        for step in 0.. {
+            let next = if step % 3 == 0 {
+                iter.next_back()
+            } else {
+                iter.next()
+            };
+            let Some((k, _)) = next else {
+                break;
+            };
+            seen.push(*k);
+        }
+        seen.sort_unstable();
+        assert_eq!(seen, (0..62).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn extract_if_takes_even_values_and_keeps_the_rest() {
+        let mut table: HashTable<usize, usize> = (0..100).map(|i| (i, i * 3)).collect();
+        let mut extracted: Vec<_> = table.extract_if(|_, v| *v % 2 == 0).collect();
+        extracted.sort_unstable();
+
+        let expected: Vec<_> = (0..100).step_by(2).map(|i| (i, i * 3)).collect();
+        assert_eq!(extracted, expected);
+        assert_eq!(table.len(), 50);
+        for i in 0..100 {
+            let expected = (i % 2 == 1).then_some(i * 3);
+            assert_eq!(table.get(&i).copied(), expected);
+        }
+        #[cfg(debug_assertions)]
+        table.assert_invariants();
+    }
+
+    #[test]
+    fn hash_map_converts_into_a_table_with_the_same_entries() {
+        let map: std::collections::HashMap<String, usize> =
+            (0..500).map(|i| (format!("key{}", i), i)).collect();
+        let table = HashTable::from(map.clone());
+
+        assert_eq!(table.len(), map.len());
+        for (k, v) in &map {
+            assert_eq!(table.get(k), Some(v));
+        }
+        for (k, v) in table.iter() {
+            assert_eq!(map.get(k), Some(v));
+        }
+    }
+
+    #[test]
+    fn table_round_trips_through_a_hash_map() {
+        let table: HashTable<usize, String> = (0..300).map(|i| (i, i.to_string())).collect();
+        let map = std::collections::HashMap::from(table);
+        assert_eq!(map.len(), 300);
+
+        let back = HashTable::from(map);
+        assert_eq!(back.len(), 300);
+        for i in 0..300 {
+            assert_eq!(back.get(&i), Some(&i.to_string()));
+        }
+    }
+
+    #[test]
+    fn multi_field_keys_hash_field_by_field() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Account {
+            id: u32,
+            name: String,
+        }
+
+        impl Hashable for Account {
+            fn hash(&self) -> usize {
+                let mut h = DjbHasher::new();
+                self.hash_into(&mut h);
+                h.finish()
+            }
+
+            fn hash_into(&self, h: &mut impl StreamingHasher) {
+                self.id.hash_into(h);
+                self.name.hash_into(h);
+            }
+        }
+
+        let account = Account {
+            id: 7,
+            name: "ada".to_string(),
+        };
+        let mut manual = DjbHasher::new();
+        manual.write(&7usize.to_ne_bytes());
+        manual.write(b"ada");
+        manual.write(&3usize.to_ne_bytes());
+        assert_eq!(account.hash(), manual.finish());
+
+        let mut table = HashTable::<Account, usize>::with_hasher(Fnv1aHasher::new());
+        table.insert(account.clone(), 1);
+        assert_eq!(table.get(&account), Some(&1));
+    }
+
+    #[test]
+    fn take_returns_the_stored_key_of_a_normalized_key() {
+        // Compares and hashes case-insensitively, so two spellings are
+        // one key.
+        #[derive(Debug, Clone)]
+        struct Name(String);
+
+        impl PartialEq for Name {
+            fn eq(&self, other: &Self) -> bool {
+                self.0.eq_ignore_ascii_case(&other.0)
+            }
+        }
+
+        impl Hashable for Name {
+            fn hash(&self) -> usize {
+                self.0.to_ascii_lowercase().hash()
+            }
+        }
+
+        let mut table = HashTable::<Name, usize>::new();
+        table.insert(Name("Alice".to_string()), 1);
+        table.insert(Name("ALICE".to_string()), 2);
+        assert_eq!(table.len(), 1);
+
+        let (key, value) = table.take(&Name("alice".to_string())).unwrap();
+        assert_eq!(key.0, "Alice");
+        assert_eq!(value, 2);
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn reserve_exact_spares_a_bulk_insert_every_resize() {
+        let mut reserved = HashTable::<usize, usize>::new();
+        reserved.reserve_exact(10_000);
+        let mut unreserved = HashTable::<usize, usize>::new();
+        for i in 0..10_000 {
+            reserved.insert(i, i);
+            unreserved.insert(i, i);
+        }
+
+        assert_eq!(reserved.capacity(), 10_000);
+        assert_eq!(reserved.resize_count(), 0);
+        assert!(unreserved.resize_count() > 0);
+    }
+
+    #[test]
+    fn is_full_flips_exactly_at_the_resize_threshold() {
+        let mut table = HashTable::<usize, usize>::with_capacity(11);
+        for i in 0..table.resize_threshold() {
+            assert!(!table.is_full());
+            table.insert(i, i);
+        }
+        assert!(table.is_full());
+        assert_eq!(table.len(), table.resize_threshold());
+
+        table.insert(100, 100);
+        assert!(!table.is_full());
+        assert_eq!(table.resize_count(), 1);
+    }
+
+    #[test]
+    fn raw_slots_cover_every_slot_and_every_entry() {
+        let mut table = HashTable::<usize, usize>::with_capacity(101);
+        for i in 0..60 {
+            table.insert(i * 7, i);
+        }
+        table.take(&14);
+
+        let slots: Vec<_> = table.raw_slots().collect();
+        assert_eq!(slots.len(), table.capacity());
+        let occupied = slots
+            .iter()
+            .filter(|slot| matches!(slot, SlotState::Occupied(..)))
+            .count();
+        assert_eq!(occupied, table.len());
+    }
+
+    #[test]
+    fn drain_keeps_the_capacity() {
+        let mut table = HashTable::<usize, usize>::new();
+        for i in 0..1000 {
+            table.insert(i, i);
+        }
+        let capacity = table.capacity();
+
+        let mut drained: Vec<_> = table.drain().collect();
+        drained.sort_unstable();
+        assert_eq!(drained, (0..1000).map(|i| (i, i)).collect::<Vec<_>>());
+        assert!(table.is_empty());
+        assert_eq!(table.capacity(), capacity);
+    }
+
+    #[test]
+    fn remove_slots_drops_those_entries_and_keeps_the_rest_reachable() {
+        let mut table = HashTable::<usize, usize>::with_capacity(31);
+        for i in 0..20 {
+            table.insert(i * 31, i);
+        }
+        let doomed = [0, 5, 19];
+        let slots: Vec<_> = doomed
+            .iter()
+            .map(|&i| table.slot_of(&(i * 31)).unwrap())
+            .collect();
+
+        table.remove_slots(&slots);
+        assert_eq!(table.len(), 17);
+        for i in 0..20 {
+            let expected = (!doomed.contains(&i)).then_some(i);
+            assert_eq!(table.get(&(i * 31)).copied(), expected);
+        }
+        #[cfg(debug_assertions)]
+        table.assert_invariants();
+    }
+
+    #[test]
+    #[should_panic(expected = "is not occupied")]
+    fn remove_slots_panics_on_an_empty_slot() {
+        let mut table = HashTable::<usize, usize>::with_capacity(11);
+        table.insert(0, 0);
+        let occupied = table.slot_of(&0).unwrap();
+        table.remove_slots(&[occupied, (occupied + 1) % 11]);
+    }
+
+    #[test]
+    fn insert_lands_where_next_free_slot_for_predicts() {
+        let mut table = HashTable::<usize, usize>::with_capacity(11);
+        for i in 0..5 {
+            table.insert(i * 11, i);
+        }
+
+        // A new key colliding with the chain goes past it...
+        let predicted = table.next_free_slot_for(&55);
+        table.insert(55, 5);
+        assert_eq!(table.slot_of(&55), Some(predicted));
+        assert_eq!(predicted, 5);
+
+        // ...and an existing key stays put.
+        assert_eq!(table.next_free_slot_for(&22), table.slot_of(&22).unwrap());
+        table.insert(22, 20);
+        assert_eq!(table.slot_of(&22), Some(2));
+    }
+
+    #[test]
+    fn get2_mut_swaps_two_values_and_refuses_to_alias_one() {
+        let mut table = HashTable::<&str, usize>::new();
+        table.insert("a", 1);
+        table.insert("b", 2);
+
+        let (Some(a), Some(b)) = table.get2_mut(&"a", &"b") else {
+            panic!("both keys are present");
+        };
+        std::mem::swap(a, b);
+        assert_eq!(table.get(&"a"), Some(&2));
+        assert_eq!(table.get(&"b"), Some(&1));
+
+        let (first, second) = table.get2_mut(&"a", &"a");
+        assert_eq!(first, Some(&mut 2));
+        assert_eq!(second, None);
+        assert_eq!(table.get2_mut(&"a", &"z"), (Some(&mut 2), None));
+        assert_eq!(table.get2_mut(&"z", &"b"), (None, Some(&mut 1)));
+    }
+
+    #[test]
+    fn shrink_to_fit_stops_at_a_custom_min_capacity() {
+        let mut table = HashTable::<usize, usize>::new();
+        table.set_min_capacity(500);
+        for i in 0..1000 {
+            table.insert(i, i);
+        }
+        table.retain(|k, _| *k < 10);
+
+        table.shrink_to_fit();
+        assert_eq!(table.capacity(), 500);
+        for i in 0..10 {
+            assert_eq!(table.get(&i), Some(&i));
+        }
+
+        table.set_min_capacity(5);
+        table.shrink_to_fit();
+        assert_eq!(table.capacity(), 21);
+    }
+
+    #[test]
+    fn owned_cow_keys_are_found_by_borrowed_cows() {
+        let mut table = HashTable::<Cow<'static, str>, usize>::new();
+        table.insert(Cow::Owned("owned".to_string()), 1);
+        table.insert(Cow::Borrowed("borrowed"), 2);
+
+        assert_eq!(table.get(&Cow::Borrowed("owned")), Some(&1));
+        assert_eq!(table.get(&Cow::Owned("borrowed".to_string())), Some(&2));
+        assert_eq!(table.get("owned"), Some(&1));
+
+        table.insert(Cow::Borrowed("owned"), 3);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get(&Cow::Owned("owned".to_string())), Some(&3));
+    }
+
+    #[test]
+    fn vec_keys_are_found_by_slices() {
+        assert_eq!(vec![1u32, 2, 3].hash(), [1u32, 2, 3][..].hash());
+
+        let mut table = HashTable::<Vec<u32>, &str>::new();
+        table.insert(vec![1, 2, 3], "one two three");
+        table.insert(vec![], "nothing");
+
+        let key: &[u32] = &[1, 2, 3];
+        assert_eq!(table.get(key), Some(&"one two three"));
+        assert_eq!(table.get(&[][..]), Some(&"nothing"));
+        assert_eq!(table.get(&[1u32, 2][..]), None);
+    }
+
+    #[test]
+    fn update_changes_present_keys_only() {
+        let mut table = HashTable::<&str, usize>::new();
+        table.insert("hits", 1);
+
+        assert!(table.update(&"hits", |v| *v += 1));
+        assert_eq!(table.get(&"hits"), Some(&2));
+
+        let mut called = false;
+        assert!(!table.update(&"misses", |_| called = true));
+        assert!(!called);
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn get_or_insert_with_key_reports_whether_it_inserted() {
+        let mut table = HashTable::<String, usize>::new();
+
+        let (len, inserted) = table.get_or_insert_with_key("four".to_string(), |k| k.len());
+        assert_eq!((*len, inserted), (4, true));
+        *len += 10;
+
+        let (len, inserted) = table.get_or_insert_with_key("four".to_string(), |_| unreachable!());
+        assert_eq!((*len, inserted), (14, false));
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn insert_if_absent_keeps_existing_values() {
+        let mut table = HashTable::<&str, usize>::new();
+        assert!(table.insert_if_absent("a", 1));
+        assert!(!table.insert_if_absent("a", 2));
+        assert_eq!(table.get(&"a"), Some(&1));
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn slice_hashes_depend_on_element_order() {
+        assert_ne!([1u32, 2, 3][..].hash(), [3u32, 2, 1][..].hash());
+        assert_ne!(vec![1u32, 2, 3].hash(), vec![3u32, 2, 1].hash());
+    }
+
+    #[test]
+    fn str_literals_are_keys() {
+        let mut table = HashTable::<&'static str, usize>::new();
+        for (i, word) in ["alpha", "beta", "gamma"].into_iter().enumerate() {
+            table.insert(word, i);
+        }
+
+        assert_eq!(table.get(&"beta"), Some(&1));
+        let owned = String::from("gamma");
+        assert_eq!(table.get(&owned.as_str()), Some(&2));
+        assert_eq!(table.get(&"delta"), None);
+        assert_eq!(table.take(&"alpha"), Some(("alpha", 0)));
+    }
+
+    #[test]
+    fn fold_values_visits_every_value_once() {
+        let table: HashTable<usize, usize> = (1..=100).map(|i| (i, i)).collect();
+        assert_eq!(table.fold_values(0, |sum, v| sum + v), 5050);
+        assert_eq!(table.fold_values(0, |count, _| count + 1), 100);
+
+        let empty = HashTable::<usize, usize>::new();
+        assert_eq!(empty.fold_values(7, |sum, v| sum + v), 7);
+    }
+
+    #[test]
+    fn retain_slots_passes_the_slot_each_entry_occupies() {
+        let mut table = HashTable::<usize, usize>::with_capacity(101);
+        for i in 0..50 {
+            table.insert(i * 3, i);
+        }
+        let slots: Vec<_> = (0..50).map(|i| table.slot_of(&(i * 3)).unwrap()).collect();
+
+        let mut visited = 0;
+        table.retain_slots(|slot, key, value| {
+            assert_eq!(slots[key / 3], slot);
+            visited += 1;
+            *value += 1000;
+            slot % 2 == 0
+        });
+        assert_eq!(visited, 50);
+
+        for (i, &slot) in slots.iter().enumerate() {
+            let expected = (slot % 2 == 0).then_some(i + 1000);
+            assert_eq!(table.get(&(i * 3)).copied(), expected);
+        }
+        #[cfg(debug_assertions)]
+        table.assert_invariants();
+    }
+
+    #[test]
+    fn with_entries_keeps_the_last_value_of_a_duplicate_key() {
+        let table = HashTable::with_entries([("a", 1), ("b", 2), ("a", 3)]);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get(&"a"), Some(&3));
+        assert_eq!(table.get(&"b"), Some(&2));
+    }
+
+    #[test]
+    fn slot_of_and_value_at_slot_mut_edit_without_probing() {
+        let mut table = HashTable::<usize, usize>::with_capacity(11);
+        for i in 0..5 {
+            table.insert(i * 11, i);
+        }
+
+        let slot = table.slot_of(&33).unwrap();
+        *table.value_at_slot_mut(slot).unwrap() = 300;
+        assert_eq!(table.get(&33), Some(&300));
+
+        assert_eq!(table.slot_of(&44), Some(4));
+        assert_eq!(table.slot_of(&55), None);
+        assert_eq!(table.value_at_slot_mut(10), None);
+        assert_eq!(table.value_at_slot_mut(11), None);
+    }
+
+    #[test]
+    fn try_for_each_value_mut_stops_at_the_first_error() {
+        let mut table: HashTable<usize, usize> = (0..10).map(|i| (i, i)).collect();
+        let mut visited = 0;
+        let result = table.try_for_each_value_mut(|v| {
+            if visited == 4 {
+                return Err(*v);
+            }
+            visited += 1;
+            *v += 100;
+            Ok(())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(visited, 4);
+        assert_eq!(table.values().filter(|&&v| v >= 100).count(), 4);
+
+        let ok: Result<(), ()> = table.try_for_each_value_mut(|v| {
+            *v = 0;
+            Ok(())
+        });
+        assert_eq!(ok, Ok(()));
+        assert_eq!(table.fold_values(0, |sum, v| sum + v), 0);
+    }
+
+    #[test]
+    fn get_many_looks_up_each_key() {
+        let table = HashTable::with_entries([("a", 1), ("b", 2)]);
+        assert_eq!(
+            table.get_many([&"a", &"z", &"b"]),
+            [Some(&1), None, Some(&2)]
+        );
+        assert_eq!(table.get_many([&"a", &"a"]), [Some(&1), Some(&1)]);
+        assert_eq!(table.get_many::<0>([]), []);
+    }
+
+    #[test]
+    fn find_key_searches_by_value() {
+        let table = HashTable::with_entries([("one", 1), ("two", 2), ("three", 3)]);
+        assert_eq!(table.find_key(|&v| v == 2), Some(&"two"));
+        assert_eq!(table.find_key(|&v| v > 10), None);
+
+        let first = table.find_key(|&v| v > 1).unwrap();
+        assert!(*first == "two" || *first == "three");
+    }
+
+    #[test]
+    fn unit_is_a_key_with_one_value() {
+        let mut table = HashTable::<(), usize>::new();
+        assert_eq!(table.get(&()), None);
+        table.insert((), 1);
+        table.insert((), 2);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.get(&()), Some(&2));
+        assert_eq!(table.take(&()), Some(((), 2)));
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn contains_all_needs_every_key() {
+        let table: HashTable<usize, ()> = (0..10).map(|i| (i, ())).collect();
+        assert!(table.contains_all(&[1, 5, 9]));
+        assert!(!table.contains_all(&[1, 50, 9]));
+        assert!(table.contains_all(&[]));
+        assert!(table.contains_all(table.keys()));
+    }
+
+    #[test]
+    fn enum_keys_hash_by_discriminant() {
+        #[derive(Debug, Clone, PartialEq)]
+        enum Shape {
+            Circle(u32),
+            Square(u32),
+            Point,
+        }
+
+        impl HashableByDiscriminant for Shape {
+            fn payload_hash(&self) -> usize {
+                match self {
+                    Shape::Circle(r) => r.hash(),
+                    Shape::Square(side) => side.hash(),
+                    Shape::Point => 0,
+                }
+            }
+        }
+
+        impl Hashable for Shape {
+            fn hash(&self) -> usize {
+                self.hash_by_discriminant()
+            }
+        }
+
+        assert_ne!(Shape::Circle(3).hash(), Shape::Square(3).hash());
+        assert_eq!(
+            Shape::Circle(3).payload_hash(),
+            Shape::Square(3).payload_hash()
+        );
+
+        let mut table = HashTable::<Shape, &str>::new();
+        table.insert(Shape::Circle(3), "circle");
+        table.insert(Shape::Square(3), "square");
+        table.insert(Shape::Point, "point");
+        assert_eq!(table.get(&Shape::Circle(3)), Some(&"circle"));
+        assert_eq!(table.get(&Shape::Square(3)), Some(&"square"));
+        assert_eq!(table.get(&Shape::Point), Some(&"point"));
+        assert_eq!(table.get(&Shape::Circle(4)), None);
+    }
+
+    #[test]
+    fn to_vec_copies_every_entry() {
+        let table: HashTable<String, usize> = (0..20).map(|i| (i.to_string(), i)).collect();
+        let mut entries = table.to_vec();
+        assert_eq!(entries.len(), table.len());
+
+        entries.sort_unstable_by_key(|&(_, v)| v);
+        let expected: Vec<_> = (0..20).map(|i| (i.to_string(), i)).collect();
+        assert_eq!(entries, expected);
+        assert_eq!(table.len(), 20);
+    }
 }